@@ -23,12 +23,33 @@ type Control = Arc<Mutex<ThreadControl>>;
 
 #[retry]
 async fn write_to_file(ctr:Control) -> RetryResult<(),()> {
-    // loop until we know the background function has created the file handle that we are simulating contestion for
-    loop {
-        let mg = ctr.lock().await;
-        if *mg == ThreadControl::RunRetry {
-            break
+    // wait until we know the background function has created the file handle that we are simulating contestion for
+    let started = RetryPolicy::builder()
+        .limit(RetryLimit::Unlimited)
+        .backoff_policy(constant_backoff)
+        .base_delay(5)
+        .deadline(2000)
+        .build();
+    let polled = started
+        .poll_until(move || {
+            let ctr = ctr.clone();
+            async move {
+                match *ctr.lock().await {
+                    ThreadControl::RunRetry => PollResult::<(), ()>::Ready(()),
+                    ThreadControl::RunBackground => PollResult::Pending,
+                }
+            }
+        })
+        .await;
+
+    // if the file handle still isn't there, this attempt fails and the retry policy decides when to wait again
+    match polled {
+        Ok(()) => {}
+        Err(PollError::TimedOut { attempts, elapsed }) => {
+            info!("fg_thread: background thread not started after {attempts} checks ({elapsed:?})");
+            return Retry(());
         }
+        Err(PollError::Failed(())) => return Abort(()),
     }
 
 
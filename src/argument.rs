@@ -0,0 +1,39 @@
+/// Arguments of #[retry] and #[retry_prepare] functions that are passed by value are cloned for every attempt
+/// through this trait. It is implemented for every Clone type; its purpose is to report a targeted error
+/// pointing at the argument when a type is not Clone.
+///
+/// Arguments annotated with ```#[by_ref]``` (or every argument, with ```#[retry(by_ref)]```) are lent to each
+/// attempt instead, so they don't need to be Clone
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must implement Clone to be passed by value to a retried function",
+    label = "cloned for every attempt",
+    note = "annotate the argument with #[by_ref], or use #[retry(by_ref)], to lend it to each attempt instead of cloning it"
+)]
+pub trait RetryArgument {
+    fn clone_for_attempt(&self) -> Self;
+}
+
+impl<T: Clone> RetryArgument for T {
+    fn clone_for_attempt(&self) -> Self {
+        self.clone()
+    }
+}
+
+/// An argument that has not been set yet on the builder #[retry_prepare] generates
+pub struct Missing;
+
+/// State of an argument of the builder #[retry_prepare] generates: each argument starts as Missing and becomes
+/// the argument's type once it is set. The builder can only be built, run or awaited when every argument is set
+#[diagnostic::on_unimplemented(
+    message = "an argument of type `{T}` has not been set on the builder",
+    label = "every argument must be set before the builder is built, run or awaited"
+)]
+pub trait BuilderArgument<T> {
+    fn into_argument(self) -> T;
+}
+
+impl<T> BuilderArgument<T> for T {
+    fn into_argument(self) -> T {
+        self
+    }
+}
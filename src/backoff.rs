@@ -1,13 +1,16 @@
 use crate::policy::RetryPolicy;
-pub fn exponential_backoff(policy: &RetryPolicy, attempt: u64) -> u64 {
-    let multiplier = 2u64.pow(attempt as u32 - 1);
-    policy.base_delay * multiplier
+use std::time::Duration;
+
+pub fn exponential_backoff(policy: &RetryPolicy, attempt: u64) -> Duration {
+    /* doubling saturates at Duration::MAX well before 128 doublings, so cap the loop there */
+    (1..attempt.min(128)).fold(policy.base_delay, |delay, _| delay.saturating_mul(2))
 }
 
-pub fn linear_backoff(policy: &RetryPolicy, attempt: u64) -> u64 {
-    policy.base_delay * attempt
+pub fn linear_backoff(policy: &RetryPolicy, attempt: u64) -> Duration {
+    let attempt = attempt.min(u32::MAX as u64) as u32;
+    policy.base_delay.saturating_mul(attempt)
 }
 
-pub fn constant_backoff(policy: &RetryPolicy, _attempt: u64) -> u64 {
+pub fn constant_backoff(policy: &RetryPolicy, _attempt: u64) -> Duration {
     policy.base_delay
 }
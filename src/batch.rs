@@ -0,0 +1,79 @@
+use crate::policy::RetryPolicy;
+use crate::retry_result::RetryResult;
+use crate::retryer::AttemptLoop;
+
+/// Submits a batch of items and re-submits only the items whose result was Retry, until every item has
+/// succeeded or aborted, or the policy stops retrying. Each submission of the remaining items counts as one attempt
+/// against the policy, and the policy's delay is waited between submissions.
+///
+/// submit is given the items still pending, in their original order, and must return one result per item in the same order.
+///
+/// Returns the final result of every item, in the order the items were given. Items still failing when the policy
+/// stops retrying end with the error of their last attempt
+///
+/// # Panics
+///
+/// If submit returns a different number of results than the items it was given
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let results = retry_batch(&policy, messages, async |pending: &[Message]| {
+///     queue.send_batch(pending).await
+///         .into_iter()
+///         .map(|sent| match sent {
+///             Ok(id) => Success(id),
+///             Err(e) if e.is_throttled() => Retry(e),
+///             Err(e) => Abort(e),
+///         })
+///         .collect()
+/// }).await;
+/// ```
+pub async fn retry_batch<I, T, E, F>(policy: &RetryPolicy, items: Vec<I>, mut submit: F) -> Vec<Result<T, E>>
+where
+    F: AsyncFnMut(&[I]) -> Vec<RetryResult<T, E>>,
+{
+    let mut results: Vec<Option<Result<T, E>>> = items.iter().map(|_| None).collect();
+    let mut pending: Vec<usize> = (0..items.len()).collect();
+    let mut items = items;
+    let mut attempts = AttemptLoop::new(policy);
+
+    while !items.is_empty() {
+        attempts.acquire().await;
+        let attempt = submit(&items).await;
+        assert_eq!(
+            attempt.len(),
+            items.len(),
+            "batch submission returned {} results for {} items",
+            attempt.len(),
+            items.len()
+        );
+
+        let mut failed = Vec::new();
+        for ((index, item), result) in pending.into_iter().zip(items).zip(attempt) {
+            match result {
+                RetryResult::Success(v) => results[index] = Some(Ok(v)),
+                RetryResult::Abort(e) => results[index] = Some(Err(e)),
+                RetryResult::Retry(e) => failed.push((index, item, e)),
+            }
+        }
+
+        /* the submission as a whole is the attempt: it is retried while any of its items should be */
+        let submission = match failed.is_empty() {
+            true => RetryResult::Success(()),
+            false => RetryResult::Retry(()),
+        };
+        if attempts.next(submission).await.is_some() {
+            for (index, _, e) in failed {
+                results[index] = Some(Err(e));
+            }
+            break;
+        }
+        (pending, items) = failed.into_iter().map(|(index, item, _)| (index, item)).unzip();
+    }
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every item has a final result"))
+        .collect()
+}
@@ -0,0 +1,157 @@
+use crate::retry_result::RetryResult;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps the number of attempts in flight at once against a dependency, so one slow dependency can't take every connection.
+/// Attempts wait in a queue for a free slot, for at most the queue timeout, and are rejected if none frees up in time.
+///
+/// A slot is held for the duration of a single attempt, and released while waiting between attempts.
+/// Clones share the same slots
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let bulkhead = Bulkhead::new(8).with_queue_timeout(Duration::from_millis(500));
+/// eztry::global::set_named_bulkhead("db", bulkhead);
+///
+/// #[retry(limit = 3, bulkhead = "db")]
+/// async fn load(pool: Pool, id: u64) -> RetryResult<Row, DbError> { .. }
+/// ```
+#[derive(Clone)]
+pub struct Bulkhead {
+    semaphore: Arc<Semaphore>,
+    max_concurrent: usize,
+    queue_timeout: Option<Duration>,
+}
+
+/// A slot of a Bulkhead, released when dropped
+pub struct BulkheadPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Error produced when an attempt waited longer than the queue timeout for a slot of a Bulkhead.
+///
+/// Retryable functions that use a bulkhead must have an error type that implements ```From<BulkheadRejected>```
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct BulkheadRejected {
+    pub max_concurrent: usize,
+    pub waited: Duration,
+}
+
+impl Display for BulkheadRejected {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rejected by a full bulkhead ({} attempts in flight) after waiting {:?}",
+            self.max_concurrent, self.waited
+        )
+    }
+}
+
+impl std::error::Error for BulkheadRejected {}
+
+/// Converts the rejection of an attempt by a full bulkhead into the executor's error type
+pub type OnRejected<E> = fn(BulkheadRejected) -> E;
+
+impl Bulkhead {
+    /// Allows up to max_concurrent attempts at once. Attempts wait for a slot indefinitely unless a queue timeout is set
+    ///
+    /// # Panics
+    ///
+    /// If max_concurrent is zero
+    pub fn new(max_concurrent: usize) -> Self {
+        assert!(max_concurrent > 0, "a bulkhead must allow at least one attempt at a time");
+        Bulkhead {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            queue_timeout: None,
+        }
+    }
+
+    /// Sets the longest an attempt waits for a slot before it is rejected. Duration::ZERO rejects attempts
+    /// as soon as the bulkhead is full
+    pub fn with_queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = Some(queue_timeout);
+        self
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// The number of free slots
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Waits for a free slot, for at most the queue timeout
+    pub async fn acquire(&self) -> Result<BulkheadPermit, BulkheadRejected> {
+        let started = Instant::now();
+        let semaphore = self.semaphore.clone();
+        let permit = match self.queue_timeout {
+            None => semaphore.acquire_owned().await.ok(),
+            Some(timeout) if timeout.is_zero() => semaphore.try_acquire_owned().ok(),
+            Some(timeout) => tokio::time::timeout(timeout, semaphore.acquire_owned())
+                .await
+                .ok()
+                .and_then(Result::ok),
+        };
+        permit
+            .map(|permit| BulkheadPermit { _permit: permit })
+            .ok_or(BulkheadRejected {
+                max_concurrent: self.max_concurrent,
+                waited: started.elapsed(),
+            })
+    }
+
+    /// Runs the future in a slot of the bulkhead, or returns the rejection if no slot was free in time
+    pub async fn run<F: Future>(&self, f: F) -> Result<F::Output, BulkheadRejected> {
+        let _permit = self.acquire().await?;
+        Ok(f.await)
+    }
+}
+
+impl Debug for Bulkhead {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bulkhead")
+            .field("max_concurrent", &self.max_concurrent)
+            .field("available", &self.available())
+            .field("queue_timeout", &self.queue_timeout)
+            .finish()
+    }
+}
+
+/// Runs a single attempt in a slot of the bulkhead. A rejected attempt is converted to an Abort holding the
+/// BulkheadRejected: the dependency is already saturated, so retrying would only add to its queue
+pub async fn with_bulkhead<T, E, F>(bulkhead: &Bulkhead, attempt: F) -> RetryResult<T, E>
+where
+    F: Future<Output = RetryResult<T, E>>,
+    E: From<BulkheadRejected>,
+{
+    match bulkhead.run(attempt).await {
+        Ok(result) => result,
+        Err(rejected) => RetryResult::Abort(rejected.into()),
+    }
+}
+
+/// Runs a single attempt in a slot of the bulkhead registered under the given name (see eztry::global::set_named_bulkhead).
+/// Used by #[retry(bulkhead = "name")]
+///
+/// # Panics
+///
+/// Panics if no bulkhead is registered under the name. Running the attempt without one would silently lift the cap
+/// on the dependency, e.g. when the name is misspelled or the bulkhead is registered after the first call
+pub async fn with_named_bulkhead<T, E, F>(name: &str, attempt: F) -> RetryResult<T, E>
+where
+    F: Future<Output = RetryResult<T, E>>,
+    E: From<BulkheadRejected>,
+{
+    match crate::global::get_named_bulkhead(name) {
+        Some(bulkhead) => with_bulkhead(&bulkhead, attempt).await,
+        None => panic!("no bulkhead is registered under the name '{name}'"),
+    }
+}
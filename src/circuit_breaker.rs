@@ -0,0 +1,227 @@
+use crate::retry_result::RetryResult;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Stops calling a dependency that keeps failing. After failure_threshold consecutive failed attempts the circuit opens,
+/// and attempts are rejected without running until reset_timeout has passed. The circuit is then half open: a single
+/// trial attempt is let through, closing the circuit if it succeeds and opening it again if it fails.
+///
+/// Attempts that end in Retry count as failures. Success and Abort count as successes, since the dependency answered.
+/// Clones share the same circuit
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let breaker = CircuitBreaker::new(5, Duration::from_secs(30));
+/// let result = with_circuit_breaker(&breaker, fetch(&client)).await;
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    state: Arc<Mutex<Generation>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Attempts run normally
+    Closed,
+    /// Attempts are rejected until the reset timeout has passed
+    Open,
+    /// The reset timeout has passed, a single trial attempt decides whether the circuit closes
+    HalfOpen,
+}
+
+enum Circuit {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// trial is true while the trial attempt is running
+    HalfOpen { trial: bool },
+}
+
+/// The circuit, and a counter incremented on every change of state. Permits only record their outcome in the
+/// generation they were acquired in, so an attempt that outlives its generation can't close, reopen or
+/// count towards a later one
+struct Generation {
+    circuit: Circuit,
+    number: u64,
+}
+
+impl Generation {
+    fn enter(&mut self, circuit: Circuit) {
+        self.circuit = circuit;
+        self.number += 1;
+    }
+}
+
+/// Error produced when an attempt is rejected by an open circuit.
+///
+/// Retryable functions that use a circuit breaker must have an error type that implements ```From<CircuitOpen>```
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitOpen {
+    /// How long until the circuit lets a trial attempt through
+    pub retry_after: Duration,
+}
+
+impl Display for CircuitOpen {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "circuit open, retry after {:?}", self.retry_after)
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+/// Converts the rejection of an attempt by an open circuit into the executor's error type
+pub type OnOpen<E> = fn(CircuitOpen) -> E;
+
+/// Permission for a single attempt to run. Record its outcome with success() or failure();
+/// dropping it without recording leaves the circuit as it was.
+///
+/// The outcome is ignored if the circuit changed state since the permit was acquired, e.g. an attempt started while
+/// the circuit was closed that finishes after it opened. Only the trial attempt decides whether a half open circuit closes
+pub struct CircuitPermit {
+    breaker: CircuitBreaker,
+    generation: u64,
+    recorded: bool,
+}
+
+impl CircuitPermit {
+    pub fn success(mut self) {
+        self.recorded = true;
+        let mut generation = self.breaker.lock();
+        if generation.number != self.generation {
+            return;
+        }
+        match generation.circuit {
+            Circuit::Closed { .. } => generation.circuit = Circuit::Closed { failures: 0 },
+            Circuit::HalfOpen { .. } => generation.enter(Circuit::Closed { failures: 0 }),
+            Circuit::Open { .. } => {}
+        }
+    }
+
+    pub fn failure(mut self) {
+        self.recorded = true;
+        let mut generation = self.breaker.lock();
+        if generation.number != self.generation {
+            return;
+        }
+        let failures = match generation.circuit {
+            Circuit::Closed { failures } => failures + 1,
+            Circuit::HalfOpen { .. } => self.breaker.failure_threshold,
+            Circuit::Open { .. } => return,
+        };
+        if failures >= self.breaker.failure_threshold {
+            generation.enter(Circuit::Open {
+                until: Instant::now() + self.breaker.reset_timeout,
+            });
+        } else {
+            generation.circuit = Circuit::Closed { failures };
+        }
+    }
+
+    /// Records the outcome of an attempt: Retry is a failure, Success and Abort are successes
+    pub fn record<T, E>(self, result: &RetryResult<T, E>) {
+        match result {
+            RetryResult::Retry(_) => self.failure(),
+            RetryResult::Success(_) | RetryResult::Abort(_) => self.success(),
+        }
+    }
+}
+
+impl Drop for CircuitPermit {
+    fn drop(&mut self) {
+        if !self.recorded {
+            let mut generation = self.breaker.lock();
+            if generation.number == self.generation
+                && let Circuit::HalfOpen { trial } = &mut generation.circuit
+            {
+                *trial = false;
+            }
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// # Panics
+    ///
+    /// If failure_threshold is zero
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        assert!(failure_threshold > 0, "a circuit breaker must allow at least one failure");
+        CircuitBreaker {
+            failure_threshold,
+            reset_timeout,
+            state: Arc::new(Mutex::new(Generation {
+                circuit: Circuit::Closed { failures: 0 },
+                number: 0,
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Generation> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.lock().circuit {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { until } if Instant::now() < until => CircuitState::Open,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Asks to run an attempt. Rejected while the circuit is open, or half open with a trial already running
+    pub fn try_acquire(&self) -> Result<CircuitPermit, CircuitOpen> {
+        let mut generation = self.lock();
+        let now = Instant::now();
+        match &mut generation.circuit {
+            Circuit::Closed { .. } => {}
+            Circuit::Open { until } if now < *until => {
+                return Err(CircuitOpen {
+                    retry_after: *until - now,
+                });
+            }
+            Circuit::Open { .. } => generation.enter(Circuit::HalfOpen { trial: true }),
+            Circuit::HalfOpen { trial: true } => {
+                return Err(CircuitOpen {
+                    retry_after: Duration::ZERO,
+                });
+            }
+            Circuit::HalfOpen { trial } => *trial = true,
+        }
+        Ok(CircuitPermit {
+            breaker: self.clone(),
+            generation: generation.number,
+            recorded: false,
+        })
+    }
+}
+
+impl Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("failure_threshold", &self.failure_threshold)
+            .field("reset_timeout", &self.reset_timeout)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// Runs a single attempt through the circuit breaker and records its outcome. An attempt rejected by the open circuit
+/// is converted to an Abort holding the CircuitOpen, so a retry loop stops instead of waiting out the reset timeout
+pub async fn with_circuit_breaker<T, E, F>(breaker: &CircuitBreaker, attempt: F) -> RetryResult<T, E>
+where
+    F: Future<Output = RetryResult<T, E>>,
+    E: From<CircuitOpen>,
+{
+    match breaker.try_acquire() {
+        Ok(permit) => {
+            let result = attempt.await;
+            permit.record(&result);
+            result
+        }
+        Err(open) => RetryResult::Abort(open.into()),
+    }
+}
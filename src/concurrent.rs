@@ -0,0 +1,119 @@
+use crate::executor::Executor;
+use crate::policy::RetryPolicy;
+use crate::retry_result::RetryResult;
+use crate::retryer::AttemptLoop;
+use futures_util::stream::{Stream, StreamExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A number of retries shared between many retry loops. Once it is spent, failing attempts are no longer retried,
+/// which stops a large run from multiplying the load on a service that is already failing.
+///
+/// Clones share the same budget
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    remaining: Arc<AtomicU64>,
+}
+
+impl RetryBudget {
+    pub fn new(retries: u64) -> Self {
+        RetryBudget {
+            remaining: Arc::new(AtomicU64::new(retries)),
+        }
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining.load(Ordering::Relaxed)
+    }
+
+    /// Takes one retry from the budget. Returns false if the budget is spent
+    pub fn try_acquire(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| remaining.checked_sub(1))
+            .is_ok()
+    }
+}
+
+/// Retries many independent executors with a bounded number running at once. Created with RetryPolicy::concurrent
+/// or RetryPolicy::concurrent_stream, and consumed by stream()
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let mut results = policy
+///     .concurrent(urls.iter().map(|url| fetch(client.clone(), url.clone())), 16)
+///     .budget(RetryBudget::new(100))
+///     .stream();
+/// while let Some((index, result)) = results.next().await {
+///     println!("{} finished: {:?}", urls[index], result);
+/// }
+/// ```
+pub struct Concurrent<'a, S> {
+    policy: &'a RetryPolicy,
+    executors: S,
+    limit: usize,
+    budget: Option<RetryBudget>,
+}
+
+impl<'a, S> Concurrent<'a, S> {
+    /// A limit of 0 is treated as 1
+    pub(crate) fn new(policy: &'a RetryPolicy, executors: S, limit: usize) -> Self {
+        Concurrent {
+            policy,
+            executors,
+            limit: limit.max(1),
+            budget: None,
+        }
+    }
+
+    /// Retries taken by any of the executors are taken from the budget, in addition to the policy's own limit
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Starts retrying. Yields the position of each executor in the input alongside its final result,
+    /// in the order the executors finish
+    pub fn stream<X, T, E>(self) -> impl Stream<Item = (usize, Result<T, E>)> + 'a
+    where
+        S: Stream<Item = X> + 'a,
+        X: Executor<T, E> + 'a,
+        T: 'a,
+        E: 'a,
+    {
+        let Concurrent {
+            policy,
+            executors,
+            limit,
+            budget,
+        } = self;
+        executors
+            .enumerate()
+            .map(move |(index, executor)| {
+                let budget = budget.clone();
+                async move { (index, retry_with_budget(policy, &executor, budget.as_ref()).await) }
+            })
+            .buffer_unordered(limit)
+    }
+}
+
+/// Retries like Retryer::run, except that a retry the policy allows is also taken from the budget, if there is one
+async fn retry_with_budget<T, E>(
+    policy: &RetryPolicy,
+    executor: &impl Executor<T, E>,
+    budget: Option<&RetryBudget>,
+) -> Result<T, E> {
+    let mut attempts = AttemptLoop::new(policy);
+    loop {
+        attempts.acquire().await;
+        let result = match executor.execute().await {
+            RetryResult::Retry(e) if attempts.will_retry() && budget.is_some_and(|budget| !budget.try_acquire()) => {
+                RetryResult::Abort(e)
+            }
+            result => result,
+        };
+        if let Some(result) = attempts.next(result).await {
+            return result;
+        }
+    }
+}
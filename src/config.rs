@@ -0,0 +1,204 @@
+use crate::backoff::{constant_backoff, exponential_backoff, linear_backoff};
+use crate::policy::{Jitter, RetryLimit, RetryPolicy, RetryPolicyBuilderError};
+use crate::{global, BackoffPolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Name of one of the built-in backoff functions, used where a policy needs to be serialized.
+/// Function pointers cannot be serialized, so custom backoff functions are not representable here
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffKind {
+    Constant,
+    Linear,
+    Exponential,
+}
+
+impl BackoffKind {
+    /// Returns the name used for this kind when serialized
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackoffKind::Constant => "constant",
+            BackoffKind::Linear => "linear",
+            BackoffKind::Exponential => "exponential",
+        }
+    }
+
+    /// Returns the backoff function this kind refers to
+    pub fn backoff_policy(&self) -> BackoffPolicy {
+        match self {
+            BackoffKind::Constant => constant_backoff,
+            BackoffKind::Linear => linear_backoff,
+            BackoffKind::Exponential => exponential_backoff,
+        }
+    }
+}
+
+/// Serializable description of a single RetryPolicy. All delays are in milliseconds
+///
+/// # Example (JSON)
+///
+/// ```json
+/// { "limit": { "Limited": 5 }, "base_delay": 100, "backoff": "exponential", "max_delay": 2000 }
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PolicyConfig {
+    pub limit: RetryLimit,
+    pub base_delay: u64,
+    pub backoff: BackoffKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_delay: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+    #[serde(default)]
+    pub jitter: Jitter,
+}
+
+impl PolicyConfig {
+    /// Checks that the config describes a usable policy. See RetryPolicy::validate
+    pub fn validate(&self) -> Result<(), RetryPolicyBuilderError> {
+        self.to_policy().map(|_| ())
+    }
+
+    /// Validates the config and converts it into a RetryPolicy
+    pub fn to_policy(&self) -> Result<RetryPolicy, RetryPolicyBuilderError> {
+        let policy = RetryPolicy {
+            limit: self.limit.clone(),
+            base_delay: Duration::from_millis(self.base_delay),
+            delay_time: self.backoff.backoff_policy(),
+            backoff: Some(self.backoff),
+            max_delay: self.max_delay.map(Duration::from_millis),
+            deadline: self.deadline.map(Duration::from_millis),
+            jitter: self.jitter,
+            rate_limiter: None,
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+}
+
+impl TryFrom<PolicyConfig> for RetryPolicy {
+    type Error = RetryPolicyBuilderError;
+
+    fn try_from(config: PolicyConfig) -> Result<Self, Self::Error> {
+        config.to_policy()
+    }
+}
+
+/// Serializable set of policies: an optional replacement for the global default policy
+/// and any number of named policies (see eztry::global::get_named_policy)
+///
+/// # Example (JSON)
+///
+/// ```json
+/// {
+///     "default": { "limit": "Unlimited", "base_delay": 1000, "backoff": "constant" },
+///     "named": {
+///         "database": { "limit": { "Limited": 5 }, "base_delay": 50, "backoff": "linear" }
+///     }
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PoliciesConfig {
+    #[serde(default)]
+    pub default: Option<PolicyConfig>,
+    #[serde(default)]
+    pub named: BTreeMap<String, PolicyConfig>,
+}
+
+impl PoliciesConfig {
+    /// Validates every policy in the config, returning the first invalid one
+    pub fn validate(&self) -> Result<(), PolicyConfigError> {
+        self.to_policies().map(|_| ())
+    }
+
+    /// Validates every policy and, only if all of them are valid, installs them as the global default
+    /// and named policies in a single step. On error nothing is applied.
+    ///
+    /// Named policies that are not present in the config are left untouched
+    ///
+    /// # Warning
+    ///
+    /// Like eztry::global::set_default_policy, every applied policy is leaked into the global scope
+    pub fn apply(&self) -> Result<(), PolicyConfigError> {
+        let (default, named) = self.to_policies()?;
+        global::replace_policies(default.map(Some), named, Vec::new());
+        Ok(())
+    }
+
+    /// Validates every policy and, only if all of them are valid, applies the config in place of `previous`,
+    /// the config that was applied before it, in a single step. On error nothing is applied.
+    ///
+    /// Unlike apply, only policies that differ from `previous` are installed, named policies that are in
+    /// `previous` but not in this config are removed and, if this config no longer has a default policy,
+    /// the global default policy is reset (see eztry::global::reset_default_policy)
+    ///
+    /// # Warning
+    ///
+    /// Like eztry::global::set_default_policy, every changed policy is leaked into the global scope
+    pub fn apply_replacing(&self, previous: &PoliciesConfig) -> Result<(), PolicyConfigError> {
+        let (default, named) = self.to_policies()?;
+        let default = (self.default != previous.default).then_some(default);
+        let named = named
+            .into_iter()
+            .filter(|(name, _)| self.named.get(name) != previous.named.get(name))
+            .collect();
+        let removed = previous
+            .named
+            .keys()
+            .filter(|name| !self.named.contains_key(*name))
+            .cloned()
+            .collect();
+        global::replace_policies(default, named, removed);
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn to_policies(
+        &self,
+    ) -> Result<(Option<RetryPolicy>, Vec<(String, RetryPolicy)>), PolicyConfigError> {
+        let default = match &self.default {
+            None => None,
+            Some(config) => Some(config.to_policy().map_err(|error| PolicyConfigError {
+                policy: None,
+                error,
+            })?),
+        };
+
+        let mut named = Vec::with_capacity(self.named.len());
+        for (name, config) in &self.named {
+            let policy = config.to_policy().map_err(|error| PolicyConfigError {
+                policy: Some(name.clone()),
+                error,
+            })?;
+            named.push((name.clone(), policy));
+        }
+
+        Ok((default, named))
+    }
+}
+
+/// Error returned when a PoliciesConfig contains an invalid policy
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PolicyConfigError {
+    /// Name of the invalid policy, or None for the default policy
+    pub policy: Option<String>,
+    pub error: RetryPolicyBuilderError,
+}
+
+impl Display for PolicyConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.policy {
+            None => write!(f, "default policy: {}", self.error),
+            Some(name) => write!(f, "policy '{name}': {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for PolicyConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -1,6 +1,7 @@
 use crate::policy::RetryPolicy;
 use crate::retry_result::RetryResult;
-use crate::retryer::Retryer;
+use crate::retryer::{OwnedRetryer, Retryer};
+use std::sync::Arc;
 use crate::util;
 use async_trait::async_trait;
 
@@ -8,20 +9,30 @@ use async_trait::async_trait;
 pub trait Executor<T, E>: Send + Sync {
     async fn execute(&self) -> RetryResult<T, E>;
 
-    /// Prepare the executor to be retried with the default policy. See eztry::policy::DEFAULT_POLICY.
+    /// Prepare the executor to be retried with the default policy. See eztry::global::get_default_policy and eztry::with_policy.
     /// Does not begin the retry process until run() is called on the Retryer. The policy can be updated with set_policy().
-    fn prepare(&self) -> Retryer<T, E>
+    fn prepare(&self) -> Retryer<'_, T, E>
     where
         Self: Sized,
     {
-        let pol = crate::global::get_default_policy();
         Retryer {
-            policy: util::OwnedOrRef::Ref(pol),
+            policy: crate::global::current_default_policy(),
             count: 0,
             function: Box::new(self),
+            timeout: None,
+            bulkhead: None,
         }
     }
 
+    /// Like prepare, but the Retryer takes ownership of the executor. The returned OwnedRetryer is 'static,
+    /// so it can be stored, or moved into another task with spawn()
+    fn prepare_owned(self) -> OwnedRetryer<T, E>
+    where
+        Self: Sized + 'static,
+    {
+        OwnedRetryer::new(Arc::new(self))
+    }
+
     /// Attempts to execute and retry the executor with a policy.
     async fn retry_with_policy(&self, policy: RetryPolicy) -> Result<T, E>
     where
@@ -33,6 +44,8 @@ pub trait Executor<T, E>: Send + Sync {
             policy: util::OwnedOrRef::Owned(policy),
             count: 0,
             function: Box::new(self),
+            timeout: None,
+            bulkhead: None,
         }
         .run()
         .await
@@ -47,22 +60,24 @@ pub trait Executor<T, E>: Send + Sync {
             policy: util::OwnedOrRef::Ref(policy),
             count: 0,
             function: Box::new(self),
+            timeout: None,
+            bulkhead: None,
         }
     }
 
-    /// Attempts to execute and retry the executor with the default policy. See eztry::policy::DEFAULT_POLICY.
+    /// Attempts to execute and retry the executor with the default policy. See eztry::global::get_default_policy and eztry::with_policy.
     async fn retry_with_default_policy(&self) -> Result<T, E>
     where
         Self: Sized + 'static,
         T: Send + Sync,
         E: Send + Sync,
     {
-        let pol = crate::global::get_default_policy();
-
         Retryer {
-            policy: util::OwnedOrRef::Ref(pol),
+            policy: crate::global::current_default_policy(),
             count: 0,
             function: Box::new(self),
+            timeout: None,
+            bulkhead: None,
         }
         .run()
         .await
@@ -70,3 +85,37 @@ pub trait Executor<T, E>: Send + Sync {
 }
 
 pub type AsyncFunction<'a, T, E> = Box<&'a dyn Executor<T, E>>;
+
+/// Blocking counterpart of Executor, for synchronous code. Uses the same policies,
+/// but waits between attempts with std::thread::sleep
+///
+/// # Warning
+///
+/// Sleeping blocks the thread, so blocking executors should not be retried on an async runtime's worker threads
+pub trait BlockingExecutor<T, E> {
+    fn execute(&self) -> RetryResult<T, E>;
+
+    /// Attempts to execute and retry the executor with a policy
+    fn retry_with_policy(&self, policy: RetryPolicy) -> Result<T, E>
+    where
+        Self: Sized,
+    {
+        policy.call_blocking(self)
+    }
+
+    /// Attempts to execute and retry the executor with the default policy. See eztry::global::get_default_policy and eztry::with_policy.
+    fn retry_with_default_policy(&self) -> Result<T, E>
+    where
+        Self: Sized,
+    {
+        crate::global::current_default_policy()
+            .as_ref()
+            .call_blocking(self)
+    }
+}
+
+impl<T, E, X: BlockingExecutor<T, E>> BlockingExecutor<T, E> for &X {
+    fn execute(&self) -> RetryResult<T, E> {
+        (**self).execute()
+    }
+}
@@ -0,0 +1,135 @@
+use crate::function_info::FunctionInfo;
+use crate::parser::RetryArgs;
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::{Attribute, ImplItem, Item, ItemFn, Meta, TraitItem, Visibility};
+
+/// Expands eztry::async_trait: every method marked #[retry] in the trait or impl block is expanded first, while it
+/// is still an ordinary async fn, and the block is then passed on to #[async_trait].
+///
+/// #[async_trait] on a block is expanded before the attributes of its methods, and turns each async fn into a fn
+/// returning a boxed future in a shape that is not a stable API of async-trait. Expanding #[retry] first means it
+/// never has to recognise that shape
+pub fn expand_async_trait(
+    attr: proc_macro2::TokenStream,
+    item: Item,
+) -> proc_macro2::TokenStream {
+    let item = match item {
+        Item::Impl(mut item_impl) => {
+            item_impl.items = expand_items(item_impl.items, impl_method);
+            Item::Impl(item_impl)
+        }
+        Item::Trait(mut item_trait) => {
+            item_trait.items = expand_items(item_trait.items, trait_method);
+            Item::Trait(item_trait)
+        }
+        /* anything else is reported by #[async_trait] itself */
+        item => item,
+    };
+    let args = (!attr.is_empty()).then(|| quote! { (#attr) });
+    quote! {
+        #[eztry::__private::async_trait #args]
+        #item
+    }
+}
+
+/// Whether #[async_trait] already expanded the method, which it marks with an 'async_trait lifetime. #[retry] can
+/// only be expanded before that, see expand_async_trait
+pub fn is_expanded(input_fn: &ItemFn) -> bool {
+    input_fn.sig.asyncness.is_none()
+        && input_fn
+            .sig
+            .generics
+            .lifetimes()
+            .any(|param| param.lifetime.ident == "async_trait")
+}
+
+/// Replaces each method marked #[retry] with its expansion. method returns the method an item declares, if it has a body
+fn expand_items<T: Parse>(
+    items: Vec<T>,
+    method: fn(&T) -> Option<ItemFn>,
+) -> Vec<T> {
+    let mut expanded = Vec::with_capacity(items.len());
+    for item in items {
+        let Some(mut input_fn) = method(&item) else {
+            expanded.push(item);
+            continue;
+        };
+        let Some(position) = input_fn.attrs.iter().position(is_retry) else {
+            expanded.push(item);
+            continue;
+        };
+        let attr = input_fn.attrs.remove(position);
+        let tokens = match retry_args(&attr) {
+            Ok(args) => FunctionInfo::from_function(input_fn.clone(), input_fn.to_token_stream()).expand_retry(args),
+            Err(e) => {
+                let error = e.to_compile_error();
+                quote! {
+                    #input_fn
+                    #error
+                }
+            }
+        };
+        match syn::parse2::<Items<T>>(tokens) {
+            Ok(Items(items)) => expanded.extend(items),
+            Err(e) => {
+                expanded.push(item);
+                if let Ok(Items(error)) = syn::parse2(e.to_compile_error()) {
+                    expanded.extend(error);
+                }
+            }
+        }
+    }
+    expanded
+}
+
+fn impl_method(item: &ImplItem) -> Option<ItemFn> {
+    let ImplItem::Fn(method) = item else {
+        return None;
+    };
+    Some(ItemFn {
+        attrs: method.attrs.clone(),
+        vis: method.vis.clone(),
+        sig: method.sig.clone(),
+        block: Box::new(method.block.clone()),
+    })
+}
+
+/// Only default methods have a body to retry. #[retry] on a method without one is left for the compiler to reject
+fn trait_method(item: &TraitItem) -> Option<ItemFn> {
+    let TraitItem::Fn(method) = item else {
+        return None;
+    };
+    Some(ItemFn {
+        attrs: method.attrs.clone(),
+        vis: Visibility::Inherited,
+        sig: method.sig.clone(),
+        block: Box::new(method.default.clone()?),
+    })
+}
+
+/// #[retry], or a path to it such as #[eztry::retry]
+fn is_retry(attr: &Attribute) -> bool {
+    attr.path().segments.last().is_some_and(|seg| seg.ident == "retry")
+}
+
+fn retry_args(attr: &Attribute) -> syn::Result<RetryArgs> {
+    match &attr.meta {
+        Meta::Path(_) => syn::parse2(proc_macro2::TokenStream::new()),
+        Meta::List(list) => syn::parse2(list.tokens.clone()),
+        Meta::NameValue(_) => Err(syn::Error::new_spanned(attr, "expected #[retry] or #[retry(..)]")),
+    }
+}
+
+/// The items a method expands to: the retried method, followed by compile errors if any
+struct Items<T>(Vec<T>);
+
+impl<T: Parse> Parse for Items<T> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            items.push(input.parse()?);
+        }
+        Ok(Items(items))
+    }
+}
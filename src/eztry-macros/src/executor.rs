@@ -0,0 +1,152 @@
+use crate::function_info::FunctionInfo;
+use quote::quote;
+use syn::{FnArg, ImplItem, ImplItemFn, ItemFn, ItemImpl};
+
+/// Expands #[executor] on an inherent impl block. The method marked #[attempt] (or the only async method)
+/// becomes the body of an Executor impl, and inherent retry_with_policy, retry_with_default_policy and
+/// prepare methods are added so the struct can be retried without importing the Executor trait
+pub fn expand_executor(mut item_impl: ItemImpl) -> proc_macro2::TokenStream {
+    let attempt = find_attempt(&mut item_impl);
+    match attempt.and_then(|attempt| expand_attempt(&item_impl, attempt)) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            /* keep the impl block (without #[attempt] markers) so the rest of the code still resolves its methods */
+            let error = e.to_compile_error();
+            quote! {
+                #item_impl
+                #error
+            }
+        }
+    }
+}
+
+fn expand_attempt(item_impl: &ItemImpl, attempt: ImplItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some((_, path, _)) = &item_impl.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "#[executor] must be used on an inherent impl block, not a trait impl",
+        ));
+    }
+
+    validate_attempt(&attempt)?;
+
+    let method = &attempt.sig.ident;
+    let vis = &attempt.vis;
+    let info = FunctionInfo::from_function(
+        ItemFn {
+            attrs: attempt.attrs.clone(),
+            vis: attempt.vis.clone(),
+            sig: attempt.sig.clone(),
+            block: Box::new(attempt.block.clone()),
+        },
+        quote! {},
+    );
+    if !info.retry_result_error().is_empty() || !info.returns_retry_result {
+        return Err(syn::Error::new_spanned(
+            &attempt.sig.output,
+            "the #[attempt] method must return RetryResult<T, E>",
+        ));
+    }
+    let ret_type_t = &info.ret_type_t;
+    let ret_type_e = &info.ret_type_e;
+
+    let self_ty = &item_impl.self_ty;
+    let (impl_generics, _, where_clause) = item_impl.generics.split_for_impl();
+
+    Ok(quote! {
+        #item_impl
+
+        #[async_trait]
+        impl #impl_generics eztry::prelude::Executor<#ret_type_t, #ret_type_e> for #self_ty #where_clause {
+            async fn execute(&self) -> eztry::RetryResult<#ret_type_t, #ret_type_e> {
+                self.#method().await
+            }
+        }
+
+        impl #impl_generics #self_ty #where_clause {
+            /// Attempts to run and retry the #[attempt] method with a policy. See eztry::Executor::prepare
+            #vis async fn retry_with_policy(
+                &self,
+                policy: eztry::RetryPolicy,
+            ) -> Result<#ret_type_t, #ret_type_e> {
+                let mut retryer = eztry::prelude::Executor::prepare(self);
+                retryer.set_policy(policy);
+                retryer.run().await
+            }
+
+            /// Attempts to run and retry the #[attempt] method with the default policy. See eztry::global::current_policy
+            #vis async fn retry_with_default_policy(&self) -> Result<#ret_type_t, #ret_type_e> {
+                self.retry_with_policy(eztry::global::current_policy()).await
+            }
+
+            /// Prepares the #[attempt] method to be retried with the default policy. See eztry::Executor::prepare
+            #vis fn prepare(&self) -> eztry::prelude::Retryer<'_, #ret_type_t, #ret_type_e> {
+                eztry::prelude::Executor::prepare(self)
+            }
+        }
+    })
+}
+
+/// Finds the method marked #[attempt], removing the marker, or the only async method if none is marked
+fn find_attempt(item_impl: &mut ItemImpl) -> syn::Result<ImplItemFn> {
+    let mut marked = Vec::new();
+    let mut async_methods = Vec::new();
+    for item in item_impl.items.iter_mut() {
+        let ImplItem::Fn(method) = item else {
+            continue;
+        };
+        let count = method.attrs.len();
+        method.attrs.retain(|attr| !attr.path().is_ident("attempt"));
+        if method.attrs.len() != count {
+            marked.push(method.clone());
+        }
+        if method.sig.asyncness.is_some() {
+            async_methods.push(method.clone());
+        }
+    }
+
+    match (marked.len(), async_methods.len()) {
+        (1, _) => Ok(marked.remove(0)),
+        (0, 1) => Ok(async_methods.remove(0)),
+        (0, 0) => Err(syn::Error::new_spanned(
+            &item_impl.self_ty,
+            "#[executor] needs an async method to use as the attempt body",
+        )),
+        (0, _) => Err(syn::Error::new_spanned(
+            &item_impl.self_ty,
+            "#[executor] found several async methods, mark the attempt body with #[attempt]",
+        )),
+        _ => Err(syn::Error::new_spanned(
+            &marked[1].sig,
+            "only one method can be marked #[attempt]",
+        )),
+    }
+}
+
+/// The attempt body is called as ```self.method().await``` by Executor::execute, which only has &self
+fn validate_attempt(attempt: &ImplItemFn) -> syn::Result<()> {
+    let sig = &attempt.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(sig, "the #[attempt] method must be async"));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "the #[attempt] method cannot be generic",
+        ));
+    }
+    match sig.inputs.first() {
+        Some(FnArg::Receiver(receiver))
+            if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+        _ => {
+            return Err(syn::Error::new_spanned(sig, "the #[attempt] method must take &self"));
+        }
+    }
+    if let Some(arg) = sig.inputs.iter().nth(1) {
+        return Err(syn::Error::new_spanned(
+            arg,
+            "the #[attempt] method cannot take arguments other than &self, store them in the struct instead",
+        ));
+    }
+    Ok(())
+}
@@ -1,24 +1,52 @@
 use crate::parser;
+use crate::parser::{duration_tokens, BulkheadSource, Classifier, PolicySource, RetryArgs};
 use proc_macro2::Ident;
-use quote::{format_ident, quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
 use std::collections::HashSet;
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::token::Comma;
+use syn::visit::Visit;
+use syn::visit_mut::VisitMut;
 use syn::{
-    parse_quote, FnArg, ItemFn, Pat, PatType, Type,
-    TypeReference,
+    parse_quote, Attribute, FnArg, GenericParam, Generics, ItemFn, Lifetime, LifetimeParam, Pat,
+    PatIdent, PatType, Type, TypeImplTrait, TypeReference, Visibility,
 };
 
+/// How an argument of the original function reaches each attempt
+#[derive(Clone, Copy, PartialEq)]
+enum ArgPassing {
+    /// ```&T``` arguments are copied
+    Shared,
+    /// ```&mut T``` arguments are reborrowed
+    Mutable,
+    /// Owned arguments annotated with #[by_ref] are lent to the attempt as ```&T```
+    Lent,
+    /// Other owned arguments are cloned
+    Cloned,
+}
+
 pub struct FunctionInfo {
     pub(crate) struct_name: Ident,
     pub(crate) inputs: Punctuated<FnArg, Comma>,
+    /// For each input, whether it was annotated with #[by_ref]
+    pub(crate) by_ref_args: Vec<bool>,
+    pub(crate) generics: Generics,
+    /// false for blocking functions, which are retried with BlockingExecutor and RetryPolicy::call_closure_blocking
+    pub(crate) is_async: bool,
+    pub(crate) vis: Visibility,
+    /// Attributes of the original function, including doc comments
+    pub(crate) attrs: Vec<Attribute>,
     pub(crate) ret_type_t: proc_macro2::TokenStream,
     pub(crate) ret_type_e: proc_macro2::TokenStream,
     pub(crate) output: proc_macro2::TokenStream,
+    /// Whether the declared return type is literally RetryResult<..>, as opposed to a Result or an alias
+    pub(crate) returns_retry_result: bool,
     pub(crate) original_body: syn::Block,
     pub(crate) original_tokens: proc_macro2::TokenStream,
     pub(crate) ctime_error: proc_macro2::TokenStream,
+    /// Set if the return type isn't RetryResult<T, E>, which is only required without retry_if and abort_if
+    pub(crate) ret_type_error: proc_macro2::TokenStream,
 }
 
 impl FunctionInfo {
@@ -29,12 +57,62 @@ impl FunctionInfo {
         parser::parse_function(&input_fn, original_tokens)
     }
 
+    /// Replaces ```impl Trait``` in the argument types with generic parameters, so the arguments can be stored in
+    /// the fields of a #[retry_prepare] struct. The struct is built by calling it like the original function, so
+    /// the parameters are inferred there just as the impl Trait types were
+    pub(crate) fn with_impl_trait_as_generics(mut self) -> Self {
+        struct ImplTraitToGeneric<'a> {
+            generics: &'a mut Generics,
+            count: usize,
+        }
+
+        impl VisitMut for ImplTraitToGeneric<'_> {
+            fn visit_type_mut(&mut self, ty: &mut Type) {
+                syn::visit_mut::visit_type_mut(self, ty);
+                if let Type::ImplTrait(TypeImplTrait { bounds, .. }) = ty {
+                    let param = format_ident!("__Impl{}", self.count);
+                    self.count += 1;
+                    self.generics.params.push(parse_quote! { #param: #bounds });
+                    *ty = parse_quote! { #param };
+                }
+            }
+        }
+
+        let mut visitor = ImplTraitToGeneric {
+            generics: &mut self.generics,
+            count: 0,
+        };
+        for arg in self.inputs.iter_mut() {
+            if let FnArg::Typed(PatType { ty, .. }) = arg {
+                visitor.visit_type_mut(ty);
+            }
+        }
+        self
+    }
+
+    /// Whether a type mentions ```impl Trait```, which can be used for arguments but not named in the generated code
+    fn contains_impl_trait(ty: &Type) -> bool {
+        struct FindImplTrait(bool);
+
+        impl Visit<'_> for FindImplTrait {
+            fn visit_type_impl_trait(&mut self, _: &TypeImplTrait) {
+                self.0 = true;
+            }
+        }
+
+        let mut visitor = FindImplTrait(false);
+        visitor.visit_type(ty);
+        visitor.0
+    }
+
+    /// Returns the lifetimes the arguments' types need when stored in a struct, and the types themselves,
+    /// with elided reference lifetimes replaced by 'args
     fn extract_lifetimes_with_defaults(
         inputs: &Punctuated<FnArg, Comma>,
-    ) -> (proc_macro2::TokenStream, Option<proc_macro2::TokenStream>) {
+    ) -> (Vec<Lifetime>, Vec<Type>) {
         let mut lifetimes = HashSet::new();
         let mut needs_default_lifetime = false;
-        let mut updated_types: Vec<Box<Type>> = Vec::new();
+        let mut updated_types: Vec<Type> = Vec::new();
 
         for arg in inputs {
             if let FnArg::Typed(PatType { ty, .. }) = arg {
@@ -47,7 +125,7 @@ impl FunctionInfo {
                     }) => {
                         if let Some(lifetime) = lifetime {
                             lifetimes.insert(lifetime.clone());
-                            updated_types.push(ty.clone()); // Keep the existing lifetime
+                            updated_types.push(*ty.clone()); // Keep the existing lifetime
                         } else {
                             needs_default_lifetime = true;
                             let new_ty = Type::Reference(TypeReference {
@@ -56,30 +134,20 @@ impl FunctionInfo {
                                 mutability: *mutability,
                                 and_token: Default::default(),
                             });
-                            updated_types.push(Box::new(new_ty));
+                            updated_types.push(new_ty);
                         }
                     }
-                    _ => updated_types.push(ty.clone()),
+                    _ => updated_types.push(*ty.clone()),
                 }
             }
         }
 
-        let lifetime_tokens = if !lifetimes.is_empty() {
-            let lifetimes_vec: Vec<_> = lifetimes.into_iter().collect();
-            quote! { <#(#lifetimes_vec),*> }
-        } else if needs_default_lifetime {
-            quote! { <'args> }
-        } else {
-            quote! {}
-        };
-
-        let updated_types_tokens = if needs_default_lifetime {
-            Some(quote! { #(#updated_types),* })
-        } else {
-            None
-        };
+        let mut lifetimes: Vec<Lifetime> = lifetimes.into_iter().collect();
+        if needs_default_lifetime {
+            lifetimes.push(parse_quote! { 'args });
+        }
 
-        (lifetime_tokens, updated_types_tokens)
+        (lifetimes, updated_types)
     }
 
     pub(crate) fn expand_prepared(&self) -> proc_macro2::TokenStream {
@@ -92,13 +160,15 @@ impl FunctionInfo {
         let output = &self.output;
         let body = &self.original_body;
         let original_tokens = &self.original_tokens;
-        let _ctime_err = &self.ctime_error;
+        let _ctime_err = self.retry_result_error();
         let inner_fn_name = format_ident!("{}_inner", struct_name);
 
         let use_ctime_error = !_ctime_err.is_empty();
 
-        let mut struct_fields = Self::get_arg_types(inputs);
-        let param_names = Self::get_struct_field_names(inputs);
+        let vis = &self.vis;
+        let passing = Self::get_arg_passing(inputs, &self.by_ref_args, false);
+        let param_names = Self::get_executor_args(inputs, &passing);
+        let inner_inputs = Self::get_inner_inputs(inputs, &passing);
 
         let has_self = Self::is_self(inputs);
 
@@ -113,34 +183,105 @@ impl FunctionInfo {
             };
         }
 
-        let (lifetimes, revised_fields) = Self::extract_lifetimes_with_defaults(inputs);
+        if let Some(FnArg::Typed(PatType { ty, .. })) = inputs
+            .iter()
+            .zip(&passing)
+            .find(|(_, passing)| **passing == ArgPassing::Mutable)
+            .map(|(arg, _)| arg)
+        {
+            let err_no_mut_ref = quote_spanned! {ty.span()=>
+                compile_error!("#[retry_prepare] cannot hold &mut arguments, since the prepared executor is only borrowed immutably. Use #[retry] instead.");
+            };
 
-        if revised_fields.is_some() {
-            struct_fields = revised_fields.unwrap()
+            return quote! {
+                #err_no_mut_ref
+                #original_tokens
+            };
         }
 
-        let anon_lifetime = if !lifetimes.is_empty() {
-            quote! { <'_> }
-        } else {
-            quote! {}
-        };
+        let (lifetimes, field_types) = Self::extract_lifetimes_with_defaults(inputs);
+        let struct_fields = quote! { #(#vis #field_types),* };
 
-        let expanded = quote! {
-            #[allow(non_camel_case_types)]
-            struct #struct_name #lifetimes (#struct_fields);
-            #[async_trait]
-            impl eztry::prelude::Executor<#ret_type_t, #ret_type_e> for #struct_name #anon_lifetime {
-                async fn execute( &self  ) -> #output
-                {
-                    async fn #inner_fn_name #lifetimes (#inputs) -> #output #body
-                    
-                   #inner_fn_name(#param_names)
-                                .await
+        /* docs and cfgs describe the struct that replaces the function, anything else (e.g. #[instrument])
+        applies to the function body, which runs once per attempt */
+        let (struct_attrs, fn_attrs): (Vec<_>, Vec<_>) =
+            self.attrs.iter().partition(|attr| Self::is_item_attr(attr));
+        let cfg_attrs: Vec<&Attribute> = struct_attrs
+            .iter()
+            .copied()
+            .filter(|attr| attr.path().is_ident("cfg") || attr.path().is_ident("cfg_attr"))
+            .collect();
+
+        /* the struct takes the function's generics, plus any lifetimes its reference fields need */
+        let fn_generics = &self.generics;
+        let fn_where_clause = &fn_generics.where_clause;
+        let mut struct_generics = fn_generics.clone();
+        for lifetime in lifetimes {
+            if !struct_generics.lifetimes().any(|l| l.lifetime == lifetime) {
+                struct_generics
+                    .params
+                    .insert(0, GenericParam::Lifetime(LifetimeParam::new(lifetime)));
+            }
+        }
+        let (_, ty_generics, struct_where_clause) = struct_generics.split_for_impl();
+
+        /* Executor requires Send + Sync, BlockingExecutor doesn't */
+        let mut executor_generics = struct_generics.clone();
+        if self.is_async {
+            let type_params: Vec<_> = executor_generics
+                .type_params()
+                .map(|param| param.ident.clone())
+                .collect();
+            let executor_where_clause = executor_generics.make_where_clause();
+            for param in type_params {
+                executor_where_clause
+                    .predicates
+                    .push(parse_quote! { #param: Send + Sync });
+            }
+        }
+        let (impl_generics, _, impl_where_clause) = executor_generics.split_for_impl();
+
+        let executor_impl = if self.is_async {
+            quote! {
+                #[async_trait]
+                impl #impl_generics eztry::prelude::Executor<#ret_type_t, #ret_type_e> for #struct_name #ty_generics #impl_where_clause {
+                    async fn execute( &self  ) -> #output
+                    {
+                        #(#fn_attrs)*
+                        async fn #inner_fn_name #fn_generics (#inner_inputs) -> #output #fn_where_clause #body
+
+                       #inner_fn_name(#param_names)
+                                    .await
+                    }
                 }
             }
+        } else {
+            quote! {
+                impl #impl_generics eztry::prelude::BlockingExecutor<#ret_type_t, #ret_type_e> for #struct_name #ty_generics #impl_where_clause {
+                    fn execute(&self) -> #output {
+                        #(#fn_attrs)*
+                        fn #inner_fn_name #fn_generics (#inner_inputs) -> #output #fn_where_clause #body
 
+                        #inner_fn_name(#param_names)
+                    }
+                }
+            }
+        };
 
+        let builder = self.expand_builder(
+            &field_types,
+            &struct_generics,
+            &executor_generics,
+            &cfg_attrs,
+        );
 
+        let expanded = quote! {
+            #(#struct_attrs)*
+            #[allow(non_camel_case_types)]
+            #vis struct #struct_name #struct_generics (#struct_fields) #struct_where_clause;
+            #(#cfg_attrs)*
+            #executor_impl
+            #builder
         };
 
         if use_ctime_error {
@@ -154,78 +295,407 @@ impl FunctionInfo {
         }
     }
 
-    pub(crate) fn expand_retry(&self, policy_fn: Option<Ident>) -> proc_macro2::TokenStream {
+    /// Generates ```name::builder()```, returning a ```name_builder``` with a setter for each argument, plus the
+    /// policy and (for async functions) the timeout of each attempt.
+    ///
+    /// The builder is a typestate: each argument is a type parameter of the builder that starts as
+    /// eztry::argument::Missing and becomes the argument's type when its setter is called, so building (or running,
+    /// or awaiting) a builder with a missing argument doesn't compile. Arguments named like one of the builder's own
+    /// methods get a setter with an ```_arg``` suffix, e.g. ```policy_arg```
+    fn expand_builder(
+        &self,
+        field_types: &[Type],
+        struct_generics: &Generics,
+        executor_generics: &Generics,
+        cfg_attrs: &[&Attribute],
+    ) -> proc_macro2::TokenStream {
+        let struct_name = &self.struct_name;
+        let builder_name = format_ident!("{}_builder", struct_name);
+        let vis = &self.vis;
+        let ret_type_t = &self.ret_type_t;
+        let ret_type_e = &self.ret_type_e;
+
+        let field_names: Vec<Ident> = self
+            .inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, arg)| match arg {
+                FnArg::Typed(PatType { pat, .. }) => Some(match &**pat {
+                    Pat::Ident(PatIdent { ident, subpat: None, .. }) => ident.clone(),
+                    _ => format_ident!("arg{}", i),
+                }),
+                FnArg::Receiver(_) => None,
+            })
+            .collect();
+        let setter_names: Vec<Ident> = field_names
+            .iter()
+            .map(|name| match ["policy", "timeout", "timeout_with", "build", "run"].iter().any(|reserved| name == reserved) {
+                true => format_ident!("{}_arg", name),
+                false => name.clone(),
+            })
+            .collect();
+        if let Some(clash) = setter_names
+            .iter()
+            .enumerate()
+            .find(|(i, setter)| field_names[*i] != **setter && field_names.contains(setter))
+            .map(|(_, setter)| setter)
+        {
+            let message = format!(
+                "cannot generate a builder: the setter for `{}` would be named `{clash}`, like another argument. Rename one of them",
+                clash.to_string().trim_end_matches("_arg")
+            );
+            return quote_spanned! {clash.span()=> compile_error!(#message); };
+        }
+
+        let states: Vec<Ident> = (0..field_names.len())
+            .map(|i| format_ident!("__Arg{}", i))
+            .collect();
+        let struct_args: Vec<proc_macro2::TokenStream> = struct_generics
+            .params
+            .iter()
+            .map(|param| match param {
+                GenericParam::Lifetime(param) => param.lifetime.to_token_stream(),
+                GenericParam::Type(param) => param.ident.to_token_stream(),
+                GenericParam::Const(param) => param.ident.to_token_stream(),
+            })
+            .collect();
+        let builder_ty = |states: &[proc_macro2::TokenStream]| {
+            quote! { #builder_name<#(#struct_args,)* #(#states),*> }
+        };
+        let state_params: Vec<proc_macro2::TokenStream> = states.iter().map(|state| quote! { #state }).collect();
+        let self_ty = builder_ty(&state_params);
+        let missing_ty = builder_ty(&vec![quote! { eztry::argument::Missing }; states.len()]);
+        let with_states = |generics: &Generics| {
+            let mut generics = generics.clone();
+            for state in &states {
+                generics.params.push(parse_quote! { #state });
+            }
+            generics
+        };
+        /* the fields are the states, so the builder refers to the struct's generics through PhantomData */
+        let builder_generics = with_states(struct_generics);
+        let executor_builder_generics = with_states(executor_generics);
+
+        let (struct_impl_generics, struct_ty_generics, struct_where_clause) = struct_generics.split_for_impl();
+        let (impl_generics, _, where_clause) = builder_generics.split_for_impl();
+        let (executor_impl_generics, _, executor_where_clause) = executor_builder_generics.split_for_impl();
+        let set_bounds = quote! {
+            #(#states: eztry::argument::BuilderArgument<#field_types>,)*
+        };
+
+        let setters = setter_names.iter().enumerate().map(|(i, setter)| {
+            let field_type = &field_types[i];
+            let mut next_states = state_params.clone();
+            next_states[i] = quote! { #field_type };
+            let next_ty = builder_ty(&next_states);
+            let fields = field_names.iter().enumerate().map(|(j, name)| match i == j {
+                true => quote! { #name: value },
+                false => quote! { #name: self.#name },
+            });
+            quote! {
+                #vis fn #setter(self, value: #field_type) -> #next_ty {
+                    #builder_name {
+                        #(#fields,)*
+                        __policy: self.__policy,
+                        __timeout: self.__timeout,
+                        __generics: std::marker::PhantomData,
+                    }
+                }
+            }
+        });
+
+        let into_future = if self.is_async {
+            self.expand_builder_into_future(&self_ty, &states, field_types, struct_generics, &executor_builder_generics)
+        } else {
+            quote! {}
+        };
+
+        let (timeout_field, timeout_init, timeout_setter, run) = if self.is_async {
+            (
+                quote! { Option<(std::time::Duration, eztry::timeout::OnTimeout<#ret_type_e>)> },
+                quote! { None },
+                /* timeout() is provided by the trait, bounded on From<TimeoutError> there rather than on the concrete
+                error type here, so the builder compiles for any error type */
+                quote! {
+                    #(#cfg_attrs)*
+                    impl #impl_generics eztry::timeout::TimeoutBuilder for #self_ty #where_clause {
+                        type Error = #ret_type_e;
+
+                        fn timeout_with(
+                            mut self,
+                            timeout: std::time::Duration,
+                            on_timeout: eztry::timeout::OnTimeout<#ret_type_e>,
+                        ) -> Self {
+                            self.__timeout = Some((timeout, on_timeout));
+                            self
+                        }
+                    }
+                },
+                quote! {
+                    /// Builds the executor and retries it with the configured policy, or the default policy if none was set
+                    #vis async fn run(mut self) -> Result<#ret_type_t, #ret_type_e>
+                    where
+                        #set_bounds
+                    {
+                        let policy = self.__policy.take();
+                        let timeout = self.__timeout.take();
+                        let executor = self.build();
+                        let mut retryer = eztry::prelude::Executor::prepare(&executor);
+                        if let Some(policy) = policy {
+                            retryer.set_policy(policy);
+                        }
+                        if let Some((timeout, on_timeout)) = timeout {
+                            retryer.set_timeout_with(timeout, on_timeout);
+                        }
+                        retryer.run().await
+                    }
+                },
+            )
+        } else {
+            (
+                quote! { () },
+                quote! { () },
+                quote! {},
+                quote! {
+                    /// Builds the executor and retries it with the configured policy, or the default policy if none was set
+                    #vis fn run(mut self) -> Result<#ret_type_t, #ret_type_e>
+                    where
+                        #set_bounds
+                    {
+                        let policy = self.__policy.take();
+                        let executor = self.build();
+                        match policy {
+                            Some(policy) => policy.call_blocking(&executor),
+                            None => eztry::prelude::BlockingExecutor::retry_with_default_policy(&executor),
+                        }
+                    }
+                },
+            )
+        };
+
+        quote! {
+            #(#cfg_attrs)*
+            impl #struct_impl_generics #struct_name #struct_ty_generics #struct_where_clause {
+                /// Returns a builder that binds the arguments by name
+                #vis fn builder() -> #missing_ty {
+                    #builder_name {
+                        #(#field_names: eztry::argument::Missing,)*
+                        __policy: None,
+                        __timeout: #timeout_init,
+                        __generics: std::marker::PhantomData,
+                    }
+                }
+            }
+
+            #(#cfg_attrs)*
+            #[allow(non_camel_case_types)]
+            #vis struct #builder_name #builder_generics #where_clause {
+                #(#field_names: #states,)*
+                __policy: Option<eztry::RetryPolicy>,
+                __timeout: #timeout_field,
+                __generics: std::marker::PhantomData<(#(#field_types,)*)>,
+            }
+
+            #(#cfg_attrs)*
+            impl #impl_generics #self_ty #where_clause {
+                #(#setters)*
+
+                /// Sets the policy to retry with, instead of the default policy
+                #vis fn policy(mut self, policy: eztry::RetryPolicy) -> Self {
+                    self.__policy = Some(policy);
+                    self
+                }
+
+                /// Builds the prepared executor. Only available once every argument has been set
+                #vis fn build(self) -> #struct_name #struct_ty_generics
+                where
+                    #set_bounds
+                {
+                    #struct_name(#(eztry::argument::BuilderArgument::into_argument(self.#field_names)),*)
+                }
+            }
+
+            #timeout_setter
+
+            #(#cfg_attrs)*
+            impl #executor_impl_generics #self_ty #executor_where_clause {
+                #run
+            }
+
+            #(#cfg_attrs)*
+            #into_future
+        }
+    }
+
+    /// Awaiting an async builder runs it. The boxed future lives as long as the builder's shortest lifetime
+    /// (or 'static if it has none), so every other generic parameter, including the argument states, must outlive it
+    fn expand_builder_into_future(
+        &self,
+        builder_ty: &proc_macro2::TokenStream,
+        states: &[Ident],
+        field_types: &[Type],
+        struct_generics: &Generics,
+        executor_builder_generics: &Generics,
+    ) -> proc_macro2::TokenStream {
+        let ret_type_t = &self.ret_type_t;
+        let ret_type_e = &self.ret_type_e;
+        let future_lifetime = struct_generics
+            .lifetimes()
+            .next()
+            .map(|param| param.lifetime.clone())
+            .unwrap_or_else(|| Lifetime::new("'static", proc_macro2::Span::call_site()));
+
+        let mut future_generics = executor_builder_generics.clone();
+        let outlives: Vec<proc_macro2::TokenStream> = struct_generics
+            .params
+            .iter()
+            .filter_map(|param| match param {
+                GenericParam::Lifetime(param) if param.lifetime != future_lifetime => {
+                    let lifetime = &param.lifetime;
+                    Some(quote! { #lifetime: #future_lifetime })
+                }
+                GenericParam::Type(param) => {
+                    let ident = &param.ident;
+                    Some(quote! { #ident: #future_lifetime })
+                }
+                _ => None,
+            })
+            .collect();
+        let where_clause = future_generics.make_where_clause();
+        for predicate in outlives {
+            where_clause.predicates.push(parse_quote! { #predicate });
+        }
+        for (state, field_type) in states.iter().zip(field_types) {
+            where_clause.predicates.push(parse_quote! {
+                #state: eztry::argument::BuilderArgument<#field_type> + Send + #future_lifetime
+            });
+        }
+        /* rustc rejects a where clause that names no generic parameter and doesn't hold, such as `Rc<u8>: Send`,
+        where it is declared. The unused higher-ranked lifetime makes these two clauses generic, so they are only
+        checked where the builder is awaited: a builder for a non-Send result still compiles, and can be run with run().
+        The body relies on them holding, to box run() as a Send future, so they can't move to a helper trait */
+        where_clause
+            .predicates
+            .push(parse_quote! { for<'__into_future> #ret_type_t: Send + #future_lifetime });
+        where_clause
+            .predicates
+            .push(parse_quote! { for<'__into_future> #ret_type_e: Send + #future_lifetime });
+
+        let (impl_generics, _, where_clause) = future_generics.split_for_impl();
+        quote! {
+            impl #impl_generics std::future::IntoFuture for #builder_ty #where_clause {
+                type Output = Result<#ret_type_t, #ret_type_e>;
+                type IntoFuture = eztry::retryer::RetryFuture<#future_lifetime, #ret_type_t, #ret_type_e>;
+
+                fn into_future(self) -> Self::IntoFuture {
+                    Box::pin(self.run())
+                }
+            }
+        }
+    }
+
+    /// The compile error of a function that must return RetryResult<T, E>, if any
+    pub(crate) fn retry_result_error(&self) -> &proc_macro2::TokenStream {
+        if self.ctime_error.is_empty() {
+            &self.ret_type_error
+        } else {
+            &self.ctime_error
+        }
+    }
+
+    pub(crate) fn expand_retry(&self, args: RetryArgs) -> proc_macro2::TokenStream {
+        let RetryArgs {
+            policy,
+            timeout,
+            classifier,
+            by_ref,
+            bulkhead,
+        } = args;
+        let passing = Self::get_arg_passing(&self.inputs, &self.by_ref_args, by_ref);
+
+        if !self.ctime_error.is_empty() {
+            let original_tokens = &self.original_tokens;
+            let ctime_error = &self.ctime_error;
+            return quote! {
+                #original_tokens
+                #ctime_error
+            };
+        }
+
+        if !self.is_async {
+            return self.expand_blocking_retry(policy, timeout, bulkhead, classifier, &passing);
+        }
+
+        if let Some(classifier) = classifier {
+            return self.expand_classified_retry(policy, timeout, bulkhead, classifier, &passing);
+        }
+
         let fn_name = &self.struct_name;
         let inputs = &self.inputs;
+        let vis = &self.vis;
+        let attrs = &self.attrs;
         let ret_type_t = &self.ret_type_t;
         let ret_type_e = &self.ret_type_e;
         let output = quote! { Result<#ret_type_t, #ret_type_e> };
         let body = &self.original_body;
 
-        let struct_fields = Self::get_arg_types(inputs);
-        let param_names = Self::get_struct_field_names(inputs);
-        let arg_names = Self::get_arg_names(inputs);
+        let struct_fields = Self::get_arg_types(inputs, &Visibility::Inherited);
+        let param_names = Self::get_executor_args(inputs, &passing);
+        let inner_inputs = Self::get_inner_inputs(inputs, &passing);
+        let (wrapper_inputs, arg_names) = Self::get_wrapper_inputs(inputs);
         let is_self = Self::is_self(inputs);
-        let without_receiver = Self::args_without_receiver(inputs);
-        let policy_call = Self::get_policy_call(&policy_fn);
+        let has_references = passing
+            .iter()
+            .any(|p| matches!(p, ArgPassing::Shared | ArgPassing::Mutable));
+        let policy_call = Self::get_policy_call(&policy);
 
-        let _ctime_err = &self.ctime_error;
+        let _ctime_err = &self.ret_type_error;
         let original_tokens = &self.original_tokens;
-        if !self.ctime_error.is_empty() {
+        if !_ctime_err.is_empty() {
             quote! {
                 #original_tokens
                 #_ctime_err
             }
-        } else if is_self {
-            let policy = if policy_fn.is_none() {
-                quote! { RetryPolicy::default() }
-            } else {
-                quote! { #policy_fn() }
-            };
+        } else if is_self
+            || has_references
+            || !self.generics.params.is_empty()
+            || Self::has_impl_trait_args(inputs)
+        {
+            /* methods can't declare an executor struct that refers to Self, and trait impls can't gain an extra
+            inner method, so the body is retried inline in a loop that borrows self (mutably for &mut self).
+            Functions taking references or generics use the same path: an executor struct would need to be
+            'static, and Send + Sync for every type parameter, to be retried with retry_with_policy.
+            So do functions taking impl Trait arguments, which can't be the type of a struct field */
+            let attempt_bindings = Self::get_attempt_bindings(inputs, &passing);
+            let attempt = Self::guard_attempt(quote! { async #body }, timeout, &bulkhead);
 
-            let formatted_inner_fn_name = format_ident!("{fn_name}__inner__");
-
-            quote! {
-                    async fn #formatted_inner_fn_name(#inputs) -> RetryResult<#ret_type_t, #ret_type_e>
-                       #body
-
-                   async fn #fn_name(#inputs) -> Result<#ret_type_t, #ret_type_e> {
-                       let policy = #policy; /*default if not supplied in macro, otherwise use f()*/
-                       let mut i = 0;
-                       loop {
-                           i+=1;
-                           let r = self.#formatted_inner_fn_name(#without_receiver).await;
-
-                           match r {
-                               eztry::RetryResult::Success(s) => {
-                                   return Ok(s);
-                               }
-                               eztry::RetryResult::Retry(e) => {
-                                   if !policy.can_retry(i) {
-                                       return Err(e)
-                                   } else {
-                                       policy.wait(i).await
-                                   }
-                               }
-                               eztry::RetryResult::Abort(e) => {
-                                   return Err(e)
-                               }
-                           }
-                       }
-                   }
-            }
+            self.async_wrapper(
+                &wrapper_inputs,
+                &output,
+                Self::attempt_loop(
+                    &policy,
+                    quote! {
+                        let __result: eztry::RetryResult<#ret_type_t, #ret_type_e> = async {
+                            #attempt_bindings
+                            #attempt.await
+                        }.await;
+                    },
+                ),
+            )
         } else {
+            let attempt = Self::guard_attempt(quote! { __inner__(#param_names) }, timeout, &bulkhead);
             quote! {
-               async fn #fn_name(#inputs) -> #output {
+                #(#attrs)*
+                #vis async fn #fn_name(#wrapper_inputs) -> #output {
                     #[allow(non_camel_case_types)]
                     struct __inner__struct(#struct_fields);
-                    async fn  __inner__(#inputs) -> RetryResult<#ret_type_t, #ret_type_e> #body
+                    async fn  __inner__(#inner_inputs) -> RetryResult<#ret_type_t, #ret_type_e> #body
 
                     #[async_trait]
                     impl eztry::prelude::Executor<#ret_type_t, #ret_type_e> for __inner__struct {
                        async fn execute( & self) -> RetryResult<#ret_type_t, #ret_type_e>
                         {
-                            __inner__(#param_names).await
+                            #attempt.await
                         }
                     }
 
@@ -236,96 +706,394 @@ impl FunctionInfo {
         }
     }
 
-    fn get_policy_call(policy_fn: &Option<Ident>) -> proc_macro2::TokenStream {
-        if let Some(policy_fn) = policy_fn {
-            quote! { ex.retry_with_policy(#policy_fn()).await }
-        } else {
-            quote! { ex.retry_with_default_policy().await }
+    /// Expands #[retry(retry_if = ..)] and #[retry(abort_if = ..)] on functions returning a plain Result.
+    /// The value and error types are never named, so the declared return type can be an alias such as anyhow::Result<T>
+    fn expand_classified_retry(
+        &self,
+        policy: PolicySource,
+        timeout: Option<std::time::Duration>,
+        bulkhead: Option<BulkheadSource>,
+        classifier: Classifier,
+        passing: &[ArgPassing],
+    ) -> proc_macro2::TokenStream {
+        let inputs = &self.inputs;
+        let output = &self.output;
+        let body = &self.original_body;
+        let original_tokens = &self.original_tokens;
+        let (wrapper_inputs, _) = Self::get_wrapper_inputs(inputs);
+
+        if self.returns_retry_result {
+            let err = quote_spanned! {output.span()=>
+                compile_error!("retry_if and abort_if classify the errors of functions returning Result<T, E>. Functions returning RetryResult<T, E> already classify their own results.");
+            };
+            return quote! {
+                #original_tokens
+                #err
+            };
         }
+
+        let attempt_bindings = Self::get_attempt_bindings(inputs, passing);
+        let classified = classifier.classify(quote! { __result });
+        let attempt = Self::guard_attempt(
+            quote! {
+                async {
+                    let __result: #output = async #body.await;
+                    #classified
+                }
+            },
+            timeout,
+            &bulkhead,
+        );
+
+        self.async_wrapper(
+            &wrapper_inputs,
+            output,
+            Self::attempt_loop(
+                &policy,
+                quote! {
+                    let __result = async {
+                        #attempt_bindings
+                        #attempt.await
+                    }.await;
+                },
+            ),
+        )
     }
 
-    fn get_arg_names(inputs: &Punctuated<FnArg, Comma>) -> proc_macro2::TokenStream {
-        let arg_names = inputs.iter().filter_map(|arg| {
-            if let FnArg::Typed(PatType { pat, .. }) = arg {
-                if let Pat::Ident(ident) = &**pat {
-                    Some(quote! { #ident })
-                } else {
-                    None
+    /// Retries attempts inline with eztry::retryer::AttemptLoop. The attempt must bind its RetryResult to __result.
+    /// An async closure would be simpler, but its future can't be proven Send, which #[async_trait] methods
+    /// and spawned tasks require
+    fn attempt_loop(
+        policy: &PolicySource,
+        attempt: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let policy = policy.policy_expr();
+        quote! {
+            let policy: eztry::RetryPolicy = #policy;
+            let mut __attempts = eztry::retryer::AttemptLoop::new(&policy);
+            loop {
+                __attempts.acquire().await;
+                #attempt
+                if let Some(result) = __attempts.next(__result).await {
+                    break result;
                 }
-            } else {
-                None
             }
-        });
+        }
+    }
+
+    /// Declares the function that replaces the original async function. Inside eztry::async_trait blocks this
+    /// runs before #[async_trait] boxes the method, so it is an ordinary async fn there too
+    fn async_wrapper(
+        &self,
+        wrapper_inputs: &Punctuated<FnArg, Comma>,
+        output: &proc_macro2::TokenStream,
+        body: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let fn_name = &self.struct_name;
+        let generics = &self.generics;
+        let where_clause = &self.generics.where_clause;
+        let vis = &self.vis;
+        let attrs = &self.attrs;
 
-        quote! { #(#arg_names),* }
+        quote! {
+            #(#attrs)*
+            #vis async fn #fn_name #generics (#wrapper_inputs) -> #output #where_clause {
+                #body
+            }
+        }
     }
 
-    fn is_self(inputs: &Punctuated<FnArg, Comma>) -> bool {
-        let first_input = inputs.first();
-        match first_input {
-            None => false,
-            Some(input) => matches!(input, FnArg::Receiver(_)),
+    /// Expands #[retry] on a function that isn't async. The body is retried as a closure with
+    /// RetryPolicy::call_closure_blocking, which sleeps the thread between attempts
+    fn expand_blocking_retry(
+        &self,
+        policy: PolicySource,
+        timeout: Option<std::time::Duration>,
+        bulkhead: Option<BulkheadSource>,
+        classifier: Option<Classifier>,
+        passing: &[ArgPassing],
+    ) -> proc_macro2::TokenStream {
+        let fn_name = &self.struct_name;
+        let inputs = &self.inputs;
+        let generics = &self.generics;
+        let where_clause = &self.generics.where_clause;
+        let vis = &self.vis;
+        let attrs = &self.attrs;
+        let ret_type_t = &self.ret_type_t;
+        let ret_type_e = &self.ret_type_e;
+        let output = &self.output;
+        let body = &self.original_body;
+        let original_tokens = &self.original_tokens;
+        let (wrapper_inputs, _) = Self::get_wrapper_inputs(inputs);
+        let attempt_bindings = Self::get_attempt_bindings(inputs, passing);
+
+        if timeout.is_some() {
+            let err = quote_spanned! {self.struct_name.span()=>
+                compile_error!("timeout is not supported on blocking functions, since a blocking attempt cannot be interrupted");
+            };
+            return quote! {
+                #original_tokens
+                #err
+            };
+        }
+
+        if bulkhead.is_some() {
+            let err = quote_spanned! {self.struct_name.span()=>
+                compile_error!("bulkhead is not supported on blocking functions, since waiting for a slot would block the thread");
+            };
+            return quote! {
+                #original_tokens
+                #err
+            };
+        }
+
+        let (wrapper_output, attempt) = match classifier {
+            Some(_) if self.returns_retry_result => {
+                let err = quote_spanned! {output.span()=>
+                    compile_error!("retry_if and abort_if classify the errors of functions returning Result<T, E>. Functions returning RetryResult<T, E> already classify their own results.");
+                };
+                return quote! {
+                    #original_tokens
+                    #err
+                };
+            }
+            Some(classifier) => {
+                let classified = classifier.classify(quote! { __result });
+                (
+                    quote! { #output },
+                    quote! {
+                        let __result: #output = (|| -> #output #body)();
+                        #classified
+                    },
+                )
+            }
+            None if !self.ret_type_error.is_empty() => {
+                let ctime_error = &self.ret_type_error;
+                return quote! {
+                    #original_tokens
+                    #ctime_error
+                };
+            }
+            None => (
+                quote! { Result<#ret_type_t, #ret_type_e> },
+                quote! { (|| -> eztry::RetryResult<#ret_type_t, #ret_type_e> #body)() },
+            ),
+        };
+        let policy = policy.policy_expr();
+
+        quote! {
+            #(#attrs)*
+            #vis fn #fn_name #generics (#wrapper_inputs) -> #wrapper_output #where_clause {
+                let policy: eztry::RetryPolicy = #policy;
+                policy.call_closure_blocking(|| {
+                    #attempt_bindings
+                    #attempt
+                })
+            }
         }
     }
 
-    fn get_struct_field_names(inputs: &Punctuated<FnArg, Comma>) -> proc_macro2::TokenStream {
-        let first_input = inputs.first();
-        let mut skip_first = false;
-        match first_input {
-            None => {}
-            Some(input) => match input {
-                FnArg::Receiver(_) => {
-                    skip_first = true;
-                }
-                FnArg::Typed(PatType { .. }) => {}
-            },
+    /// Wraps a future producing a single attempt's RetryResult in eztry::timeout::with_timeout, if a timeout was given,
+    /// and then in eztry::bulkhead::with_bulkhead, if a bulkhead was given. Time spent waiting for a slot isn't
+    /// part of the attempt's timeout
+    fn guard_attempt(
+        attempt: proc_macro2::TokenStream,
+        timeout: Option<std::time::Duration>,
+        bulkhead: &Option<BulkheadSource>,
+    ) -> proc_macro2::TokenStream {
+        let attempt = match timeout {
+            None => attempt,
+            Some(timeout) => {
+                let timeout = duration_tokens(timeout);
+                quote! { eztry::timeout::with_timeout(#timeout, #attempt) }
+            }
+        };
+        match bulkhead {
+            None => attempt,
+            Some(bulkhead) => bulkhead.guard(attempt),
         }
+    }
 
-        let param_names = (0..inputs.len()).filter_map(|i| {
-            if skip_first && i == 0 {
-                return None;
+    fn get_policy_call(policy: &PolicySource) -> proc_macro2::TokenStream {
+        match policy {
+            PolicySource::Default => quote! { ex.retry_with_default_policy().await },
+            _ => {
+                let policy = policy.policy_expr();
+                quote! { ex.retry_with_policy(#policy).await }
             }
+        }
+    }
 
-            let index = syn::Index::from(i);
-            Some(quote! { self.#index.clone() })
+    /// Rebinds the arguments at the start of every attempt of a closure-retried function: owned arguments are
+    /// cloned so each attempt gets its own copy (or lent, with #[by_ref]), and mutable references are reborrowed.
+    /// The original patterns are used for the bindings, so destructured arguments are destructured again on every attempt
+    fn get_attempt_bindings(
+        inputs: &Punctuated<FnArg, Comma>,
+        passing: &[ArgPassing],
+    ) -> proc_macro2::TokenStream {
+        let bindings = inputs.iter().zip(passing).enumerate().filter_map(|(i, (arg, passing))| {
+            let FnArg::Typed(PatType { pat, ty, .. }) = arg else {
+                return None;
+            };
+            let ident = Self::wrapper_arg_name(pat, i);
+            let is_plain_ident = matches!(&**pat, Pat::Ident(PatIdent { by_ref: None, subpat: None, mutability: None, .. }));
+            let value = match passing {
+                ArgPassing::Shared if is_plain_ident => return None,
+                ArgPassing::Shared => quote! { #ident },
+                ArgPassing::Mutable => quote! { &mut *#ident },
+                ArgPassing::Lent => quote! { &#ident },
+                ArgPassing::Cloned => Self::clone_for_attempt(ty, quote! { &#ident }),
+            };
+            Some(quote! {
+                #[allow(unused_variables)]
+                let #pat = #value;
+            })
         });
 
-        quote! {#(#param_names),*}
+        quote! { #(#bindings)* }
     }
 
-    fn get_arg_types(inputs: &Punctuated<FnArg, Comma>) -> proc_macro2::TokenStream {
-        let types = inputs.iter().filter_map(|arg| {
-            if let FnArg::Typed(PatType { ty, .. }) = arg {
-                Some(quote! { #ty })
-            } else {
-                None
+    fn get_arg_passing(
+        inputs: &Punctuated<FnArg, Comma>,
+        by_ref_args: &[bool],
+        all_by_ref: bool,
+    ) -> Vec<ArgPassing> {
+        inputs
+            .iter()
+            .zip(by_ref_args)
+            .map(|(arg, by_ref)| match arg {
+                FnArg::Receiver(_) => ArgPassing::Shared,
+                FnArg::Typed(PatType { ty, .. }) => match &**ty {
+                    Type::Reference(TypeReference {
+                        mutability: Some(_),
+                        ..
+                    }) => ArgPassing::Mutable,
+                    Type::Reference(_) => ArgPassing::Shared,
+                    _ if *by_ref || all_by_ref => ArgPassing::Lent,
+                    _ => ArgPassing::Cloned,
+                },
+            })
+            .collect()
+    }
+
+    /// Clones an argument for an attempt through eztry::argument::RetryArgument, so a type that isn't
+    /// Clone is reported at the argument's type. impl Trait types can't be named, so their type is inferred
+    fn clone_for_attempt(ty: &Type, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        if Self::contains_impl_trait(ty) {
+            quote_spanned! {ty.span()=>
+                eztry::argument::RetryArgument::clone_for_attempt(#value)
             }
-        });
+        } else {
+            quote_spanned! {ty.span()=>
+                <#ty as eztry::argument::RetryArgument>::clone_for_attempt(#value)
+            }
+        }
+    }
 
-        quote! { #(#types,)* }
+    fn has_impl_trait_args(inputs: &Punctuated<FnArg, Comma>) -> bool {
+        inputs.iter().any(|arg| match arg {
+            FnArg::Typed(PatType { ty, .. }) => Self::contains_impl_trait(ty),
+            FnArg::Receiver(_) => false,
+        })
     }
 
-    fn args_without_receiver(
+    /// Inputs of the function holding the original body: lent arguments are taken as references
+    fn get_inner_inputs(
         inputs: &Punctuated<FnArg, Comma>,
-    ) -> Option<proc_macro2::TokenStream> {
-        let first_input = inputs.first();
-        let is_self = matches!(first_input, Some(FnArg::Receiver(_)));
+        passing: &[ArgPassing],
+    ) -> Punctuated<FnArg, Comma> {
+        let mut inner_inputs = inputs.clone();
+        for (arg, passing) in inner_inputs.iter_mut().zip(passing) {
+            if let (FnArg::Typed(PatType { ty, .. }), ArgPassing::Lent) = (arg, passing) {
+                let lent: Type = parse_quote! { &#ty };
+                **ty = lent;
+            }
+        }
+        inner_inputs
+    }
+
+    /// Returns the inputs of the generated wrapper function and the names of its arguments.
+    /// Arguments bound with a pattern (```(a, b): (u32, u32)```, ```_: &str```, ```mut x: u32```) are
+    /// given a plain name in the wrapper, the pattern itself is applied inside each attempt
+    fn get_wrapper_inputs(
+        inputs: &Punctuated<FnArg, Comma>,
+    ) -> (Punctuated<FnArg, Comma>, proc_macro2::TokenStream) {
+        let mut wrapper_inputs = inputs.clone();
+        let mut arg_names = Vec::new();
+        for (i, arg) in wrapper_inputs.iter_mut().enumerate() {
+            if let FnArg::Typed(pat_type) = arg {
+                let ident = Self::wrapper_arg_name(&pat_type.pat, i);
+                *pat_type.pat = parse_quote! { #ident };
+                arg_names.push(ident);
+            }
+        }
+
+        (wrapper_inputs, quote! { #(#arg_names),* })
+    }
+
+    /// Name of an argument in the generated wrapper function: the original name for arguments bound to
+    /// a plain identifier, otherwise a generated name based on the argument's position
+    fn wrapper_arg_name(pat: &Pat, index: usize) -> Ident {
+        match pat {
+            Pat::Ident(PatIdent {
+                ident,
+                by_ref: None,
+                subpat: None,
+                ..
+            }) => ident.clone(),
+            _ => format_ident!("__arg{}", index),
+        }
+    }
 
-        if !is_self {
-            return None;
+    /// Attributes of a #[retry_prepare] function that apply to the generated struct rather than the function body
+    fn is_item_attr(attr: &Attribute) -> bool {
+        ["doc", "cfg", "cfg_attr", "allow", "deprecated"]
+            .iter()
+            .any(|name| attr.path().is_ident(name))
+    }
+
+    fn is_self(inputs: &Punctuated<FnArg, Comma>) -> bool {
+        let first_input = inputs.first();
+        match first_input {
+            None => false,
+            Some(input) => matches!(input, FnArg::Receiver(_)),
         }
+    }
 
-        let args = inputs.iter().skip(1).map(|arg| {
-            if let FnArg::Typed(PatType { pat, ty, .. }) = arg {
-                match **ty {
-                    Type::Reference(_) => quote! { &#pat },
-                    _ => quote! { #pat.clone() },
+    /// Arguments passed from the executor struct's fields to the function holding the original body
+    fn get_executor_args(
+        inputs: &Punctuated<FnArg, Comma>,
+        passing: &[ArgPassing],
+    ) -> proc_macro2::TokenStream {
+        let args = inputs
+            .iter()
+            .zip(passing)
+            .filter_map(|(arg, passing)| match arg {
+                FnArg::Typed(PatType { ty, .. }) => Some((ty, passing)),
+                FnArg::Receiver(_) => None,
+            })
+            .enumerate()
+            .map(|(i, (ty, passing))| {
+                let index = syn::Index::from(i);
+                match passing {
+                    ArgPassing::Shared | ArgPassing::Mutable => quote! { self.#index },
+                    ArgPassing::Lent => quote! { &self.#index },
+                    ArgPassing::Cloned => Self::clone_for_attempt(ty, quote! { &self.#index }),
                 }
+            });
+
+        quote! {#(#args),*}
+    }
+
+    fn get_arg_types(inputs: &Punctuated<FnArg, Comma>, vis: &Visibility) -> proc_macro2::TokenStream {
+        let types = inputs.iter().filter_map(|arg| {
+            if let FnArg::Typed(PatType { ty, .. }) = arg {
+                Some(quote! { #vis #ty })
             } else {
-                quote! {}
+                None
             }
         });
 
-        Some(quote! { #(#args),* })
+        quote! { #(#types,)* }
     }
 }
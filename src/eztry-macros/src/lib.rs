@@ -1,8 +1,10 @@
 use function_info::FunctionInfo;
+use parser::RetryArgs;
 use proc_macro::TokenStream;
-use proc_macro2::Ident;
-use syn::{parse_macro_input, ItemFn};
+use syn::{parse_macro_input, Item, ItemFn, ItemImpl};
 
+mod async_trait;
+mod executor;
 mod function_info;
 mod parser;
 
@@ -20,6 +22,8 @@ mod parser;
 ///
 /// The function will return the first Success result as a Result<T, _>
 ///
+/// Generics are kept on the struct, and ```impl Trait``` arguments become generic parameters of it
+///
 /// Example:
 /// ```ignore
 ///
@@ -51,7 +55,8 @@ mod parser;
 pub fn retry_prepare(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let original_tokens: proc_macro2::TokenStream = item.clone().into();
     let input_fn = parse_macro_input!(item as ItemFn);
-    let retryable_data = FunctionInfo::from_function(input_fn, original_tokens);
+    let retryable_data =
+        FunctionInfo::from_function(input_fn, original_tokens).with_impl_trait_as_generics();
     let expanded = retryable_data.expand_prepared();
     TokenStream::from(expanded)
 }
@@ -59,6 +64,26 @@ pub fn retry_prepare(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// Builds a retryable function with the given policy (or the default policy if none is provided).
 /// The function build will use the same name and arguments as the original function, and can be ```.await```ed directly
 ///
+/// The policy can be given as:
+///
+/// - ```#[retry]``` - the default policy, see eztry::global::current_policy
+/// - ```#[retry(policy_fn)]``` - a function returning a RetryPolicy, called on every invocation
+/// - ```#[retry("name")]``` - a policy registered with eztry::global::set_named_policy. Calling the function
+///   panics if no policy is registered under the name
+///
+/// Methods taking ```&self``` or ```&mut self``` are supported, including methods in trait impls, default trait
+/// methods and methods inside ```#[async_trait]``` traits and impls. Those must use eztry::async_trait, which expands
+/// ```#[retry]``` before the methods are boxed.
+///
+/// The visibility, attributes and doc comments of the original function are kept, and arguments may be
+/// destructured (```(a, b): (u32, u32)```, ```_: String```). Destructuring happens again on every attempt.
+///
+/// Owned arguments are cloned for every attempt. Annotate an argument with ```#[by_ref]```, or use ```#[retry(by_ref)]```
+/// for all of them, to lend it to each attempt as ```&T``` instead. Reference arguments are never cloned.
+///
+/// Functions that aren't ```async``` are retried on the current thread, sleeping between attempts with std::thread::sleep.
+/// With #[retry_prepare] they implement BlockingExecutor instead of Executor.
+///
 /// The return type of the original function must be of the form ```RetryResult<T, E>```,
 /// and the return type of the new function will be ```Result<T, E>``` where T and E are the types of the original function.
 ///
@@ -100,17 +125,72 @@ pub fn retry_prepare(_attr: TokenStream, item: TokenStream) -> TokenStream {
 ///     
 #[proc_macro_attribute]
 pub fn retry(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let policy_fn = if attr.is_empty() {
-        None
-    } else {
-        Some(parse_macro_input!(attr as Ident))
-    };
+    let args = parse_macro_input!(attr as RetryArgs);
 
     let original_tokens: proc_macro2::TokenStream = item.clone().into();
     let input_fn = parse_macro_input!(item as ItemFn);
 
     let retryable_data = FunctionInfo::from_function(input_fn, original_tokens);
-    let expanded = retryable_data.expand_retry(policy_fn);
+    let expanded = retryable_data.expand_retry(args);
+
+    TokenStream::from(expanded)
+}
 
+/// Makes a struct an Executor from an impl block. One async method taking only ```&self``` and returning
+/// ```RetryResult<T, E>``` is the attempt body: the method marked ```#[attempt]```, or the only async method in the block.
+///
+/// Besides the ```Executor<T, E>``` impl, the struct gets inherent ```retry_with_policy```, ```retry_with_default_policy```
+/// and ```prepare``` methods with the visibility of the attempt method.
+///
+/// Example:
+/// ```ignore
+///
+/// struct Fetch {
+///     url: String,
+/// }
+///
+/// #[executor]
+/// impl Fetch {
+///     #[attempt]
+///     async fn attempt(&self) -> RetryResult<String, Error> {
+///         match get(&self.url).await {
+///             Ok(body) => Success(body),
+///             Err(e) => Retry(e),
+///         }
+///     }
+/// }
+///
+/// async fn fetch() {
+///     let body = Fetch { url: "https://example.com".into() }
+///         .retry_with_policy(policy())
+///         .await;
+/// }
+///```
+#[proc_macro_attribute]
+pub fn executor(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item_impl = parse_macro_input!(item as ItemImpl);
+    let expanded = executor::expand_executor(item_impl);
+    TokenStream::from(expanded)
+}
+
+/// Same as async_trait::async_trait, which it delegates to, except that the methods marked ```#[retry]``` in the trait
+/// or impl block are expanded first, while they are still ```async fn```. A ```#[retry]``` method can't be retried once
+/// async_trait::async_trait has turned it into a function returning a boxed future.
+///
+/// Example:
+/// ```ignore
+///
+/// #[eztry::async_trait]
+/// impl Service for RemoteService {
+///     #[retry(three_quick_attempts)]
+///     async fn call(&self, request: String) -> RetryResult<u64, Error> {
+///         // ...
+///     }
+/// }
+///```
+#[proc_macro_attribute]
+pub fn async_trait(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as Item);
+    let expanded = async_trait::expand_async_trait(attr.into(), item);
     TokenStream::from(expanded)
 }
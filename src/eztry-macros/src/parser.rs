@@ -1,22 +1,53 @@
-use syn::{ItemFn, PathArguments, ReturnType, Type};
+use std::collections::HashSet;
+use std::time::Duration;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, ExprLit, ExprPath, FnArg, ItemFn, Lit, LitStr, Meta, Path, PathArguments, ReturnType, Token, Type};
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::{quote, quote_spanned, ToTokens};
 use std::ops::Deref;
 use syn::spanned::Spanned;
+use crate::async_trait;
 use crate::function_info::FunctionInfo;
 
 pub fn parse_function(input_fn: &ItemFn, original_tokens: proc_macro2::TokenStream) -> FunctionInfo {
+    let mut _ctime_err = quote! {};
+    if async_trait::is_expanded(input_fn) {
+        _ctime_err = syn::Error::new_spanned(
+            &input_fn.sig,
+            "#[retry] can't retry a method that #[async_trait] already expanded. Use eztry::async_trait on the trait or \
+             impl block instead, which expands #[retry] first",
+        )
+        .to_compile_error();
+    }
     let fn_name = &input_fn.sig.ident;
     let struct_name = fn_name; // Struct name matches function name
-    let inputs = &input_fn.sig.inputs;
+    /* #[by_ref] is consumed here, it isn't a real attribute */
+    let mut inputs = input_fn.sig.inputs.clone();
+    let mut by_ref_args = Vec::with_capacity(inputs.len());
+    for arg in inputs.iter_mut() {
+        let attrs = match arg {
+            FnArg::Typed(pat_type) => &mut pat_type.attrs,
+            FnArg::Receiver(receiver) => &mut receiver.attrs,
+        };
+        let count = attrs.len();
+        attrs.retain(|attr| !attr.path().is_ident("by_ref"));
+        by_ref_args.push(attrs.len() != count);
+    }
     let mut ret_type_t: Option<TokenStream> = None;
     let mut ret_type_e: Option<TokenStream> = None;
     let mut ctime_type_loc: Option<Span> = None;
+    let mut returns_retry_result = false;
     let output = match &input_fn.sig.output {
         ReturnType::Type(_, ty) => {
             if let Type::Path(p) = ty.deref() {
                 ctime_type_loc = Some(p.span());
+                returns_retry_result = p
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|seg| seg.ident == "RetryResult");
 
                 for seg in &p.path.segments {
                     let p2 = &seg.arguments;
@@ -38,10 +69,11 @@ pub fn parse_function(input_fn: &ItemFn, original_tokens: proc_macro2::TokenStre
         }
     };
 
-    let mut _ctime_err = quote! {};
+    /* kept apart from the other errors: retry_if and abort_if accept any Result, including aliases */
+    let mut ret_type_err = quote! {};
     if ret_type_t.is_none() || ret_type_e.is_none() {
-        let span = ctime_type_loc.unwrap();
-        _ctime_err = quote_spanned! {span=>
+        let span = ctime_type_loc.unwrap_or_else(|| input_fn.sig.span());
+        ret_type_err = quote_spanned! {span=>
                 compile_error!("Return type must be of the form RetryResult<T, E>. The retryable proc macro is unable to determine the underlying value and error types behind a type alias.");
             };
     }
@@ -52,12 +84,456 @@ pub fn parse_function(input_fn: &ItemFn, original_tokens: proc_macro2::TokenStre
 
     FunctionInfo {
         struct_name: struct_name.clone(),
-        inputs: inputs.clone(),
+        inputs,
+        by_ref_args,
+        generics: input_fn.sig.generics.clone(),
+        is_async: input_fn.sig.asyncness.is_some(),
+        vis: input_fn.vis.clone(),
+        attrs: input_fn.attrs.clone(),
         ret_type_t: ret_type_t.clone(),
         ret_type_e: ret_type_e.clone(),
         output: output.clone(),
+        returns_retry_result,
         original_body: *body.clone(),
         original_tokens: original_tokens.clone(),
         ctime_error: _ctime_err.clone(),
+        ret_type_error: ret_type_err,
+    }
+}
+
+
+
+/// Arguments of the #[retry] attribute
+pub struct RetryArgs {
+    pub policy: PolicySource,
+    /// Timeout for each individual attempt
+    pub timeout: Option<Duration>,
+    /// Classifies the errors of functions returning a plain Result
+    pub classifier: Option<Classifier>,
+    /// #[retry(by_ref)] - lend every owned argument to each attempt instead of cloning it
+    pub by_ref: bool,
+    /// Bulkhead each attempt takes a slot of
+    pub bulkhead: Option<BulkheadSource>,
+}
+
+/// #[retry(bulkhead = "name")] for a bulkhead registered with eztry::global::set_named_bulkhead,
+/// or #[retry(bulkhead = expr)] for any expression evaluating to a Bulkhead (or a reference to one)
+pub enum BulkheadSource {
+    Named(LitStr),
+    Expr(Expr),
+}
+
+impl BulkheadSource {
+    /// Wraps a future producing a single attempt's RetryResult in eztry::bulkhead::with_bulkhead
+    pub fn guard(&self, attempt: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match self {
+            BulkheadSource::Named(name) => {
+                quote! { eztry::bulkhead::with_named_bulkhead(#name, #attempt) }
+            }
+            BulkheadSource::Expr(bulkhead) => {
+                quote! { eztry::bulkhead::with_bulkhead(&#bulkhead, #attempt) }
+            }
+        }
+    }
+}
+
+/// #[retry(retry_if = predicate)] or #[retry(abort_if = predicate)], where predicate is any expression
+/// callable as ```FnOnce(&E) -> bool```
+pub enum Classifier {
+    RetryIf(Expr),
+    AbortIf(Expr),
+}
+
+impl Classifier {
+    /// An expression converting the Result named by `result` into a RetryResult
+    pub fn classify(&self, result: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        match self {
+            Classifier::RetryIf(predicate) => {
+                quote! { eztry::RetryResult::retry_if(#result, #predicate) }
+            }
+            Classifier::AbortIf(predicate) => {
+                quote! { eztry::RetryResult::abort_if(#result, #predicate) }
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Where a #[retry] function gets its policy from
+pub enum PolicySource {
+    /// #[retry] - the current default policy
+    Default,
+    /// #[retry(policy_fn)] or #[retry(policy = policy_fn)] - a function returning a RetryPolicy
+    Function(Path),
+    /// #[retry("name")] or #[retry(name = "name")] - a policy registered with eztry::global::set_named_policy
+    Named(LitStr),
+    /// #[retry(limit = 5, backoff = exponential, ...)] - a policy described by the attribute itself
+    Inline(InlinePolicy),
+}
+
+/// Policy settings given directly in the attribute. Unset fields use the RetryPolicyBuilder::new_with_defaults values
+#[derive(Default)]
+pub struct InlinePolicy {
+    /// Some(None) is an unlimited policy
+    limit: Option<Option<u64>>,
+    /// The builder call setting the backoff
+    backoff: Option<proc_macro2::TokenStream>,
+    base_delay: Option<Duration>,
+    max_delay: Option<Duration>,
+    deadline: Option<Duration>,
+    jitter: Option<proc_macro2::Ident>,
+}
+
+const RETRY_ARGUMENTS: &str =
+    "limit, backoff, base_delay, max_delay, deadline, jitter, timeout, bulkhead, policy, name, retry_if, abort_if, by_ref";
+const INLINE_POLICY_ARGUMENTS: [&str; 6] =
+    ["limit", "backoff", "base_delay", "max_delay", "deadline", "jitter"];
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(1000);
+
+impl Parse for RetryArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(RetryArgs {
+                policy: PolicySource::Default,
+                timeout: None,
+                classifier: None,
+                by_ref: false,
+                bulkhead: None,
+            });
+        }
+
+        if input.peek(LitStr) {
+            let name: LitStr = input.parse()?;
+            if !input.is_empty() {
+                return Err(input.error(
+                    "a policy name must be the only argument, use `name = \"...\"` alongside other arguments",
+                ));
+            }
+            return Ok(RetryArgs {
+                policy: PolicySource::Named(name),
+                timeout: None,
+                classifier: None,
+                by_ref: false,
+                bulkhead: None,
+            });
+        }
+
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated(input)?;
+
+        /* a lone path is the original #[retry(policy_fn)] form */
+        if metas.len() == 1
+            && let Meta::Path(path) = &metas[0]
+            && !path.is_ident("jitter")
+            && !path.is_ident("by_ref")
+        {
+            return Ok(RetryArgs {
+                policy: PolicySource::Function(path.clone()),
+                timeout: None,
+                classifier: None,
+                by_ref: false,
+                bulkhead: None,
+            });
+        }
+
+        let mut seen = HashSet::new();
+        let mut inline = InlinePolicy::default();
+        let mut inline_span = None;
+        let mut policy = None;
+        let mut timeout = None;
+        let mut classifier = None;
+        let mut by_ref = false;
+        let mut bulkhead = None;
+
+        for meta in &metas {
+            let (key, value) = match meta {
+                Meta::Path(path) if path.is_ident("jitter") || path.is_ident("by_ref") => (path, None),
+                Meta::NameValue(nv) => (&nv.path, Some(&nv.value)),
+                Meta::Path(path) => {
+                    return Err(syn::Error::new_spanned(
+                        path,
+                        "a policy function must be the only argument, use `policy = ...` alongside other arguments",
+                    ));
+                }
+                Meta::List(list) => {
+                    return Err(syn::Error::new_spanned(
+                        list,
+                        format!("expected `key = value`, valid keys are: {RETRY_ARGUMENTS}"),
+                    ));
+                }
+            };
+
+            let Some(ident) = key.get_ident() else {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    format!("unknown #[retry] argument, valid keys are: {RETRY_ARGUMENTS}"),
+                ));
+            };
+            let name = ident.to_string();
+            if !seen.insert(name.clone()) {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    format!("duplicate #[retry] argument `{name}`"),
+                ));
+            }
+            if INLINE_POLICY_ARGUMENTS.contains(&name.as_str()) && inline_span.is_none() {
+                inline_span = Some(key.clone());
+            }
+
+            match (name.as_str(), value) {
+                ("jitter", None) => inline.jitter = Some(proc_macro2::Ident::new("Full", ident.span())),
+                ("jitter", Some(value)) => inline.jitter = Some(parse_jitter(value)?),
+                ("limit", Some(value)) => inline.limit = Some(parse_limit(value)?),
+                ("backoff", Some(value)) => inline.backoff = Some(parse_backoff(value)?),
+                ("base_delay", Some(value)) => inline.base_delay = Some(parse_duration(value)?),
+                ("max_delay", Some(value)) => inline.max_delay = Some(parse_duration(value)?),
+                ("deadline", Some(value)) => inline.deadline = Some(parse_duration(value)?),
+                ("timeout", Some(value)) => {
+                    let duration = parse_duration(value)?;
+                    if duration.is_zero() {
+                        return Err(syn::Error::new_spanned(value, "timeout must be greater than zero"));
+                    }
+                    timeout = Some(duration)
+                }
+                ("bulkhead", Some(value)) => {
+                    bulkhead = Some(match value {
+                        Expr::Lit(ExprLit { lit: Lit::Str(name), .. }) => BulkheadSource::Named(name.clone()),
+                        _ => BulkheadSource::Expr(value.clone()),
+                    })
+                }
+                ("by_ref", None) => by_ref = true,
+                ("by_ref", Some(value)) => {
+                    return Err(syn::Error::new_spanned(value, "`by_ref` doesn't take a value"));
+                }
+                ("retry_if", Some(value)) => classifier = Some(Classifier::RetryIf(value.clone())),
+                ("abort_if", Some(value)) => classifier = Some(Classifier::AbortIf(value.clone())),
+                ("policy", Some(value)) => match value {
+                    Expr::Path(ExprPath { path, .. }) => {
+                        policy = Some((key, PolicySource::Function(path.clone())))
+                    }
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "expected the path of a function returning a RetryPolicy",
+                        ));
+                    }
+                },
+                ("name", Some(value)) => match value {
+                    Expr::Lit(ExprLit { lit: Lit::Str(name), .. }) => {
+                        policy = Some((key, PolicySource::Named(name.clone())))
+                    }
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "expected the name of a policy as a string literal",
+                        ));
+                    }
+                },
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        key,
+                        format!("unknown #[retry] argument `{name}`, valid keys are: {RETRY_ARGUMENTS}"),
+                    ));
+                }
+            }
+        }
+
+        if seen.contains("retry_if") && seen.contains("abort_if") {
+            return Err(syn::Error::new_spanned(
+                &metas,
+                "`retry_if` and `abort_if` cannot be used together",
+            ));
+        }
+
+        if seen.contains("policy") && seen.contains("name") {
+            let (key, _) = policy.as_ref().unwrap();
+            return Err(syn::Error::new_spanned(key, "`policy` and `name` cannot be used together"));
+        }
+
+        let policy = match (policy, inline_span) {
+            (Some((key, _)), Some(_)) => {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    "a policy function or name cannot be combined with inline policy settings \
+                     (limit, backoff, base_delay, max_delay, deadline, jitter)",
+                ));
+            }
+            (Some((_, policy)), None) => policy,
+            (None, Some(span)) => {
+                inline.validate(&span)?;
+                PolicySource::Inline(inline)
+            }
+            (None, None) => PolicySource::Default,
+        };
+
+        Ok(RetryArgs {
+            policy,
+            timeout,
+            classifier,
+            by_ref,
+            bulkhead,
+        })
+    }
+}
+
+impl InlinePolicy {
+    /// Compile time equivalent of the checks in RetryPolicy::validate that don't depend on the backoff function.
+    /// The others (an overflowing backoff, a deadline before the first retry) are left to try_build, see policy_expr
+    fn validate(&self, span: &Path) -> syn::Result<()> {
+        if self.limit == Some(Some(0)) {
+            return Err(syn::Error::new_spanned(span, "limit must allow at least one attempt"));
+        }
+        let base_delay = self.base_delay.unwrap_or(DEFAULT_BASE_DELAY);
+        if matches!(self.limit, None | Some(None)) && base_delay.is_zero() {
+            return Err(syn::Error::new_spanned(
+                span,
+                "unlimited attempts with a base_delay of 0 would retry in a hot loop",
+            ));
+        }
+        if let Some(max_delay) = self.max_delay
+            && max_delay < base_delay
+        {
+            return Err(syn::Error::new_spanned(
+                span,
+                format!("max_delay of {max_delay:?} is less than base_delay of {base_delay:?}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl PolicySource {
+    /// An expression evaluating to an owned RetryPolicy
+    pub fn policy_expr(&self) -> proc_macro2::TokenStream {
+        match self {
+            PolicySource::Default => quote! { eztry::global::current_policy() },
+            PolicySource::Function(policy_fn) => quote! { #policy_fn() },
+            PolicySource::Named(name) => quote! { eztry::global::named_policy(#name) },
+            PolicySource::Inline(inline) => {
+                let mut calls = Vec::new();
+                match inline.limit {
+                    Some(Some(limit)) => calls.push(quote! { .limit(eztry::prelude::RetryLimit::Limited(#limit)) }),
+                    Some(None) => calls.push(quote! { .limit(eztry::prelude::RetryLimit::Unlimited) }),
+                    None => {}
+                }
+                if let Some(backoff) = &inline.backoff {
+                    calls.push(backoff.clone());
+                }
+                if let Some(base_delay) = inline.base_delay {
+                    let base_delay = duration_tokens(base_delay);
+                    calls.push(quote! { .base_delay_duration(#base_delay) });
+                }
+                if let Some(max_delay) = inline.max_delay {
+                    let max_delay = duration_tokens(max_delay);
+                    calls.push(quote! { .max_delay_duration(#max_delay) });
+                }
+                if let Some(deadline) = inline.deadline {
+                    let deadline = duration_tokens(deadline);
+                    calls.push(quote! { .deadline_duration(#deadline) });
+                }
+                if let Some(jitter) = &inline.jitter {
+                    calls.push(quote! { .jitter(eztry::prelude::Jitter::#jitter) });
+                }
+                /* RetryPolicy::validate runs the backoff, so some invalid settings are only found at runtime */
+                quote! {
+                    eztry::prelude::RetryPolicyBuilder::new_with_defaults()
+                        #(#calls)*
+                        .try_build()
+                        .unwrap_or_else(|e| panic!("invalid #[retry] arguments: {e}"))
+                }
+            }
+        }
+    }
+}
+
+pub fn duration_tokens(duration: Duration) -> proc_macro2::TokenStream {
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    quote! { ::std::time::Duration::new(#secs, #nanos) }
+}
+
+fn parse_limit(value: &Expr) -> syn::Result<Option<u64>> {
+    match value {
+        Expr::Lit(ExprLit { lit: Lit::Int(int), .. }) => Ok(Some(int.base10_parse()?)),
+        Expr::Path(ExprPath { path, .. }) if path.is_ident("unlimited") => Ok(None),
+        _ => Err(syn::Error::new_spanned(
+            value,
+            "expected a number of attempts or `unlimited`",
+        )),
+    }
+}
+
+/// Built-in backoffs are set by kind so the policy can name them, other paths as backoff functions
+fn parse_backoff(value: &Expr) -> syn::Result<proc_macro2::TokenStream> {
+    let Expr::Path(ExprPath { path, .. }) = value else {
+        return Err(syn::Error::new_spanned(
+            value,
+            "expected `constant`, `linear`, `exponential` or the path of a backoff function",
+        ));
+    };
+    let builtin = [("constant", "Constant"), ("linear", "Linear"), ("exponential", "Exponential")]
+        .into_iter()
+        .find(|(name, _)| path.is_ident(name));
+    match builtin {
+        Some((_, kind)) => {
+            let kind = syn::Ident::new(kind, path.span());
+            Ok(quote! { .backoff(eztry::prelude::BackoffKind::#kind) })
+        }
+        None => Ok(quote! { .backoff_policy(#path) }),
+    }
+}
+
+fn parse_jitter(value: &Expr) -> syn::Result<proc_macro2::Ident> {
+    let variant = match value {
+        Expr::Path(ExprPath { path, .. }) if path.is_ident("full") => "Full",
+        Expr::Path(ExprPath { path, .. }) if path.is_ident("equal") => "Equal",
+        Expr::Path(ExprPath { path, .. }) if path.is_ident("none") => "None",
+        Expr::Lit(ExprLit { lit: Lit::Bool(b), .. }) if b.value => "Full",
+        Expr::Lit(ExprLit { lit: Lit::Bool(_), .. }) => "None",
+        _ => {
+            return Err(syn::Error::new_spanned(
+                value,
+                "expected `full`, `equal`, `none`, `true` or `false`",
+            ));
+        }
+    };
+    Ok(proc_macro2::Ident::new(variant, value.span()))
+}
+
+/// Parses "100ms", "5s", "1m" etc. Bare integers are milliseconds
+fn parse_duration(value: &Expr) -> syn::Result<Duration> {
+    let lit = match value {
+        Expr::Lit(ExprLit { lit: Lit::Int(int), .. }) => {
+            return Ok(Duration::from_millis(int.base10_parse()?));
+        }
+        Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }) => lit,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                value,
+                "expected a duration such as \"100ms\" or \"5s\"",
+            ));
+        }
+    };
+
+    let text = lit.value();
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let invalid = || {
+        syn::Error::new_spanned(
+            lit,
+            format!("invalid duration \"{text}\", expected a whole number followed by one of: ns, us, ms, s, m, h"),
+        )
+    };
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit.trim() {
+        "ns" => Duration::from_nanos(amount),
+        "us" | "µs" => Duration::from_micros(amount),
+        "ms" => Duration::from_millis(amount),
+        "s" => Duration::from_secs(amount),
+        "m" | "min" => Duration::from_secs(amount.checked_mul(60).ok_or_else(invalid)?),
+        "h" => Duration::from_secs(amount.checked_mul(3600).ok_or_else(invalid)?),
+        _ => return Err(invalid()),
+    };
+    Ok(duration)
+}
@@ -1,28 +1,54 @@
 #[cfg(feature = "macros")]
 extern crate eztry_macros;
+/* with the macros, eztry_macros::async_trait wraps this one so #[retry] methods are expanded first */
+#[cfg(not(feature = "macros"))]
 pub use async_trait::async_trait;
 pub use executor::Executor;
 pub use policy::RetryPolicy;
 pub use retry_result::RetryResult;
+pub use global::with_policy;
 
 #[cfg(feature = "macros")]
 pub use eztry_macros::*;
 
+pub mod argument;
 mod backoff;
+pub mod batch;
+pub mod bulkhead;
+pub mod circuit_breaker;
+pub mod concurrent;
+pub mod config;
 pub mod executor;
+pub mod pipeline;
 pub mod policy;
+pub mod poll;
+pub mod rate_limit;
 pub mod retry_result;
 pub mod retryer;
+pub mod stream;
+pub mod timeout;
+
+#[cfg(feature = "reload")]
+pub mod reload;
 
 pub mod prelude {
-    pub use crate::executor::{AsyncFunction, Executor};
-    pub use crate::policy::{RetryLimit, RetryPolicy, RetryPolicyBuilder, RetryPolicyBuilderError};
+    pub use crate::executor::{AsyncFunction, BlockingExecutor, Executor};
+    pub use crate::bulkhead::{Bulkhead, BulkheadRejected};
+    pub use crate::circuit_breaker::{CircuitBreaker, CircuitOpen, CircuitState};
+    pub use crate::pipeline::Pipeline;
+    pub use crate::rate_limit::RateLimiter;
+    pub use crate::timeout::TimeoutBuilder;
+    pub use crate::config::BackoffKind;
+    pub use crate::policy::{
+        Jitter, PolicyViolation, RetryLimit, RetryPolicy, RetryPolicyBuilder,
+        RetryPolicyBuilderError, ScheduledDelay,
+    };
     pub use crate::retry_result::{
         RetryResult, RetryResult::Abort, RetryResult::Retry, RetryResult::Success,
     };
 
     //automatically add some
-    pub use crate::retryer::{ClosureRetryer, Retryer};
+    pub use crate::retryer::{AttemptLoop, BlockingRetryer, ClosureRetryer, OwnedRetryer, Retryer};
 
     // prelude justification: very useful default methods when making retryable functions
     pub use crate::{abort, retry, success};
@@ -33,12 +59,30 @@ pub mod prelude {
     // prelude justification: adds a very useful method to async closures
     pub use crate::policy::Retryable;
 
+    // prelude justification: batches are retried without an executor, so this is the entry point
+    pub use crate::batch::retry_batch;
+
+    // prelude justification: needed to configure RetryPolicy::concurrent
+    pub use crate::concurrent::RetryBudget;
+
+    // prelude justification: streams are retried without an executor, so this is the entry point
+    pub use crate::stream::resume_stream;
+
+    // prelude justification: needed to write poll_until probes
+    pub use crate::poll::{poll_until, PollError, PollResult};
+
     #[cfg(feature = "macros")]
     pub use eztry_macros::*;
 
     #[cfg(feature = "macros")]
     pub use crate::async_trait;
 }
+/// Used by the code generated by the macros
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+}
+
 #[cfg(feature = "macros")]
 pub mod macros {
     pub use crate::async_trait;
@@ -47,18 +91,33 @@ pub mod macros {
 
 pub mod global {
     use crate::backoff::constant_backoff;
-    use crate::policy::RetryLimit;
-    use crate::util::StaticWall;
+    use crate::config::BackoffKind;
+    use crate::policy::{Jitter, RetryLimit};
+    use crate::util::{OwnedOrRef, StaticWall};
     use crate::prelude::*;
+    use std::collections::BTreeMap;
+    use std::future::Future;
     use std::ops::Deref;
     use std::sync::Mutex;
+    use std::time::Duration;
 
     const GLOBAL_DEFAULT_POLICY: RetryPolicy = RetryPolicy {
         limit: RetryLimit::Unlimited,
-        base_delay: 1000,
+        base_delay: Duration::from_millis(1000),
         delay_time: constant_backoff,
+        backoff: Some(BackoffKind::Constant),
+        max_delay: None,
+        deadline: None,
+        jitter: Jitter::None,
+        rate_limiter: None,
     };
     static DEFAULT_POLICY: Mutex<StaticWall<RetryPolicy>> = Mutex::new(StaticWall(&GLOBAL_DEFAULT_POLICY));
+    static NAMED_POLICIES: Mutex<BTreeMap<String, StaticWall<RetryPolicy>>> = Mutex::new(BTreeMap::new());
+    static NAMED_BULKHEADS: Mutex<BTreeMap<String, Bulkhead>> = Mutex::new(BTreeMap::new());
+
+    tokio::task_local! {
+        static SCOPED_POLICY: RetryPolicy;
+    }
 
     /// Sets the default policy for all retryable functions
     ///
@@ -75,7 +134,7 @@ pub mod global {
     /// Reset the default policy back to its original values:
     ///
     /// -  limit: RetryLimit::Unlimited
-    /// -  base_delay: 1000
+    /// -  base_delay: 1000ms
     /// -  delay_time: constant_backoff
     pub fn reset_default_policy() {
         let mut lock = DEFAULT_POLICY.lock().unwrap();
@@ -87,13 +146,125 @@ pub mod global {
     /// If the default policy has not been set, this will return a policy with the following defaults:
     ///
     /// 1. limit: RetryLimit::Unlimited
-    /// 2. base_delay: 1000
+    /// 2. base_delay: 1000ms
     /// 3. delay_time: constant_backoff
     pub fn get_default_policy() -> &'static RetryPolicy {
         let mx = DEFAULT_POLICY.lock().expect("Failed to lock mutex");
         let wall = mx.deref();
         wall.deref()
     }
+
+    /// Runs the future with the given policy overriding the global default policy.
+    ///
+    /// Within the future, everything that would use the global default policy (#[retry] functions without a policy,
+    /// prepare(), the '..._with_default_policy' methods) uses this policy instead. The global default policy is not
+    /// modified, so concurrent tasks and tests are unaffected. Scopes can be nested; the innermost policy wins.
+    ///
+    /// The override is task-local: tasks spawned from within the future (e.g. with tokio::spawn) do not inherit it
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let res = eztry::with_policy(fast_policy(), async {
+    ///     retryable_function(agent).await
+    /// }).await;
+    /// ```
+    pub async fn with_policy<F: Future>(policy: RetryPolicy, f: F) -> F::Output {
+        SCOPED_POLICY.scope(policy, f).await
+    }
+
+    /// Returns the policy set by the innermost enclosing with_policy scope, or the global default policy if there is none
+    pub(crate) fn current_default_policy() -> OwnedOrRef<'static, RetryPolicy> {
+        SCOPED_POLICY
+            .try_with(|policy| OwnedOrRef::Owned(policy.clone()))
+            .unwrap_or_else(|_| OwnedOrRef::Ref(get_default_policy()))
+    }
+
+    /// Returns a copy of the policy in effect for the current task: the policy of the innermost enclosing
+    /// with_policy scope, or the global default policy if there is none
+    pub fn current_policy() -> RetryPolicy {
+        current_default_policy().as_ref().clone()
+    }
+
+    /// Returns a copy of the policy registered under the given name. Used by #[retry("name")]
+    ///
+    /// # Panics
+    ///
+    /// Panics if no policy is registered under the name. Falling back to another policy would hide a misspelled
+    /// name, and the default policy retries without limit
+    pub fn named_policy(name: &str) -> RetryPolicy {
+        match get_named_policy(name) {
+            Some(policy) => policy.clone(),
+            None => panic!("no retry policy is registered under the name '{name}'"),
+        }
+    }
+
+    /// Registers a policy under a name so it can be shared by retryable functions, replacing any
+    /// policy previously registered with the same name
+    ///
+    /// # Warning
+    ///
+    /// - This will leak the provided policy into the global scope. Calling it more than once will cause a memory leak
+    pub fn set_named_policy(name: impl Into<String>, policy: RetryPolicy) {
+        let mut lock = NAMED_POLICIES.lock().unwrap();
+        lock.insert(name.into(), StaticWall::leak(policy));
+    }
+
+    /// Returns a static reference to the policy registered under the given name, if any
+    pub fn get_named_policy(name: &str) -> Option<&'static RetryPolicy> {
+        let lock = NAMED_POLICIES.lock().expect("Failed to lock mutex");
+        lock.get(name).map(|wall| wall.deref())
+    }
+
+    /// Removes the policy registered under the given name. Returns true if a policy was removed
+    pub fn remove_named_policy(name: &str) -> bool {
+        let mut lock = NAMED_POLICIES.lock().unwrap();
+        lock.remove(name).is_some()
+    }
+
+    /// Registers a bulkhead under the name of the dependency it protects, so every retryable function calling
+    /// that dependency shares its slots. Replaces any bulkhead previously registered with the same name;
+    /// attempts already holding a slot of the old bulkhead keep it
+    pub fn set_named_bulkhead(name: impl Into<String>, bulkhead: Bulkhead) {
+        let mut lock = NAMED_BULKHEADS.lock().unwrap();
+        lock.insert(name.into(), bulkhead);
+    }
+
+    /// Returns the bulkhead registered under the given name, if any. Clones share their slots with the registered bulkhead
+    pub fn get_named_bulkhead(name: &str) -> Option<Bulkhead> {
+        let lock = NAMED_BULKHEADS.lock().expect("Failed to lock mutex");
+        lock.get(name).cloned()
+    }
+
+    /// Removes the bulkhead registered under the given name. Returns true if a bulkhead was removed
+    pub fn remove_named_bulkhead(name: &str) -> bool {
+        let mut lock = NAMED_BULKHEADS.lock().unwrap();
+        lock.remove(name).is_some()
+    }
+
+    /// Replaces the default policy and the given named policies and removes the given names while holding both
+    /// locks, so readers never observe a partially applied set of policies.
+    ///
+    /// default: None keeps the current default policy, Some(None) resets it to the original values
+    pub(crate) fn replace_policies(
+        default: Option<Option<RetryPolicy>>,
+        named: Vec<(String, RetryPolicy)>,
+        removed: Vec<String>,
+    ) {
+        let mut default_lock = DEFAULT_POLICY.lock().unwrap();
+        let mut named_lock = NAMED_POLICIES.lock().unwrap();
+        match default {
+            None => {}
+            Some(Some(policy)) => *default_lock = StaticWall::leak(policy),
+            Some(None) => *default_lock = StaticWall(&GLOBAL_DEFAULT_POLICY),
+        }
+        for (name, policy) in named {
+            named_lock.insert(name, StaticWall::leak(policy));
+        }
+        for name in removed {
+            named_lock.remove(&name);
+        }
+    }
 }
 
 pub(crate) mod util {
@@ -111,6 +282,18 @@ pub(crate) mod util {
         }
     }
 
+    /// Returns a pseudo-random number in [0, 1). Only suitable for spreading out delays, not for anything
+    /// that needs real randomness
+    pub(crate) fn random_fraction() -> f64 {
+        use std::hash::{BuildHasher, Hasher};
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
     pub(crate) struct StaticWall<T: 'static>(pub(crate) &'static T);
 
     impl<T> StaticWall<T> {
@@ -124,7 +307,8 @@ pub(crate) mod util {
     }
 }
 
-pub type BackoffPolicy = fn(&RetryPolicy, u64) -> u64;
+/// Calculates the delay before the next attempt from the policy and the number of the attempt that just failed
+pub type BackoffPolicy = fn(&RetryPolicy, u64) -> std::time::Duration;
 
 /// Shorthand for RetryResult::Success(value)
 #[inline(always)]
@@ -0,0 +1,168 @@
+use crate::backoff::constant_backoff;
+use crate::bulkhead::{Bulkhead, BulkheadRejected, OnRejected};
+use crate::circuit_breaker::{CircuitBreaker, CircuitOpen, OnOpen};
+use crate::executor::Executor;
+use crate::policy::{RetryLimit, RetryPolicy};
+use crate::retry_result::RetryResult;
+use crate::retryer::{guard_attempt, AttemptLoop};
+use crate::timeout::{OnTimeout, TimeoutError};
+use std::future::Future;
+use std::time::Duration;
+
+/// Stacks the resilience layers of eztry around an executor or closure in a fixed order, from outermost to innermost:
+///
+/// 1. fallback - replaces the final error with a value
+/// 2. retry - retries attempts that end in Retry according to the policy (including its rate limiter)
+/// 3. circuit breaker - rejects attempts while the circuit is open, and records the outcome of each attempt
+/// 4. bulkhead - holds a slot for the duration of each attempt
+/// 5. timeout - bounds each attempt, a timed out attempt ends in Retry
+///
+/// Every layer is optional, and the order the builder methods are called in doesn't matter. Without retry, the attempt runs once.
+/// Attempts rejected by the circuit breaker or the bulkhead end the retry, and are passed to the fallback like any other error
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let pipeline = Pipeline::new()
+///     .timeout(Duration::from_secs(2))
+///     .retry(policy)
+///     .circuit_breaker(breaker.clone())
+///     .fallback(|_| cached_page());
+///
+/// let page = pipeline.run(&Fetch::new(client, url)).await;
+/// ```
+pub struct Pipeline<T, E> {
+    /// A single attempt unless retry() is called
+    policy: RetryPolicy,
+    timeout: Option<(Duration, OnTimeout<E>)>,
+    bulkhead: Option<(Bulkhead, OnRejected<E>)>,
+    circuit_breaker: Option<(CircuitBreaker, OnOpen<E>)>,
+    fallback: Option<Box<dyn Fn(E) -> T + Send + Sync>>,
+}
+
+impl<T, E> Default for Pipeline<T, E> {
+    fn default() -> Self {
+        Pipeline {
+            policy: RetryPolicy::builder()
+                .limit(RetryLimit::Limited(1))
+                .base_delay(0)
+                .backoff_policy(constant_backoff)
+                .build(),
+            timeout: None,
+            bulkhead: None,
+            circuit_breaker: None,
+            fallback: None,
+        }
+    }
+}
+
+impl<T, E> Pipeline<T, E> {
+    /// Creates a pipeline with no layers, which runs the attempt once
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Retries attempts that end in Retry according to the policy
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Bounds each attempt, with on_timeout converting the TimeoutError into the error type
+    pub fn timeout_with(mut self, timeout: Duration, on_timeout: OnTimeout<E>) -> Self {
+        self.timeout = Some((timeout, on_timeout));
+        self
+    }
+
+    /// Runs each attempt in a slot of the bulkhead, with on_rejected converting the BulkheadRejected into the error type
+    pub fn bulkhead_with(mut self, bulkhead: Bulkhead, on_rejected: OnRejected<E>) -> Self {
+        self.bulkhead = Some((bulkhead, on_rejected));
+        self
+    }
+
+    /// Runs each attempt through the circuit breaker, with on_open converting the CircuitOpen into the error type
+    pub fn circuit_breaker_with(mut self, circuit_breaker: CircuitBreaker, on_open: OnOpen<E>) -> Self {
+        self.circuit_breaker = Some((circuit_breaker, on_open));
+        self
+    }
+
+    /// Replaces the final error with a value, so the pipeline never fails
+    pub fn fallback(mut self, fallback: impl Fn(E) -> T + Send + Sync + 'static) -> Self {
+        self.fallback = Some(Box::new(fallback));
+        self
+    }
+
+    /// Runs the executor through the pipeline
+    pub async fn run(&self, executor: &impl Executor<T, E>) -> Result<T, E> {
+        let mut attempts = AttemptLoop::new(&self.policy);
+        let result = loop {
+            attempts.acquire().await;
+            let result = self.attempt(executor.execute()).await;
+            if let Some(result) = attempts.next(result).await {
+                break result;
+            }
+        };
+        self.fall_back(result)
+    }
+
+    /// Runs an async closure through the pipeline. The closure may mutate its captured state between attempts
+    pub async fn call(&self, mut attempt: impl AsyncFnMut() -> RetryResult<T, E>) -> Result<T, E> {
+        let mut attempts = AttemptLoop::new(&self.policy);
+        let result = loop {
+            attempts.acquire().await;
+            let result = self.attempt(attempt()).await;
+            if let Some(result) = attempts.next(result).await {
+                break result;
+            }
+        };
+        self.fall_back(result)
+    }
+
+    /// Runs a single attempt through the circuit breaker, bulkhead and timeout layers
+    async fn attempt(&self, attempt: impl Future<Output = RetryResult<T, E>>) -> RetryResult<T, E> {
+        let permit = match &self.circuit_breaker {
+            None => None,
+            Some((breaker, on_open)) => match breaker.try_acquire() {
+                Ok(permit) => Some(permit),
+                Err(open) => return RetryResult::Abort(on_open(open)),
+            },
+        };
+        /* a rejected attempt never called the dependency, so its permit is dropped without recording an outcome */
+        let result = match guard_attempt(attempt, &self.bulkhead, self.timeout).await {
+            Ok(result) => result,
+            Err(rejected) => return RetryResult::Abort(rejected),
+        };
+        if let Some(permit) = permit {
+            permit.record(&result);
+        }
+        result
+    }
+
+    fn fall_back(&self, result: Result<T, E>) -> Result<T, E> {
+        match (result, &self.fallback) {
+            (Err(e), Some(fallback)) => Ok(fallback(e)),
+            (result, _) => result,
+        }
+    }
+}
+
+impl<T, E: From<TimeoutError>> Pipeline<T, E> {
+    /// Bounds each attempt. See eztry::timeout::with_timeout
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.timeout_with(timeout, E::from)
+    }
+}
+
+impl<T, E: From<BulkheadRejected>> Pipeline<T, E> {
+    /// Runs each attempt in a slot of the bulkhead. See eztry::bulkhead::with_bulkhead
+    pub fn bulkhead(self, bulkhead: Bulkhead) -> Self {
+        self.bulkhead_with(bulkhead, E::from)
+    }
+}
+
+impl<T, E: From<CircuitOpen>> Pipeline<T, E> {
+    /// Runs each attempt through the circuit breaker. See eztry::circuit_breaker::with_circuit_breaker
+    pub fn circuit_breaker(self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker_with(circuit_breaker, E::from)
+    }
+}
@@ -1,10 +1,16 @@
 use crate::backoff::*;
-use crate::executor::Executor;
-use crate::retryer::{ClosureRetryer, Retryer};
+use crate::concurrent::Concurrent;
+use crate::config::BackoffKind;
+use crate::executor::{BlockingExecutor, Executor};
+use crate::poll::{PollError, PollResult};
+use crate::rate_limit::RateLimiter;
+use crate::retryer::{BlockingRetryer, ClosureRetryer, Retryer};
 use crate::{global, BackoffPolicy, RetryResult};
+use futures_util::stream::{self, Stream};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub enum RetryLimit {
@@ -12,11 +18,50 @@ pub enum RetryLimit {
     Limited(u64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct RetryPolicy {
     pub limit: RetryLimit,
-    pub base_delay: u64,
-    pub delay_time: fn(&RetryPolicy, u64) -> u64,
+    pub base_delay: Duration,
+    pub delay_time: BackoffPolicy,
+    /// The built-in backoff that delay_time is, used to describe and serialize the policy. None for custom backoffs
+    pub backoff: Option<BackoffKind>,
+    /// Upper bound on the delay between attempts, applied after the backoff policy
+    pub max_delay: Option<Duration>,
+    /// Total time budget measured from the first attempt.
+    /// No retry is started if its delay would end after the deadline
+    pub deadline: Option<Duration>,
+    /// Randomisation applied to each delay when waiting, so that many callers retrying at once spread out
+    pub jitter: Jitter,
+    /// Shared limit on the rate of attempts. A permit is acquired before every attempt, and the time spent waiting
+    /// for it counts towards the deadline: a retry whose permit would only be granted after the deadline is not made
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+/// Randomisation applied to the delay between attempts. Applied after max_delay, so jittered delays never exceed it
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Jitter {
+    /// Wait exactly the calculated delay
+    #[default]
+    None,
+    /// Wait a random time between zero and the calculated delay
+    Full,
+    /// Wait half the calculated delay plus a random time up to the other half
+    Equal,
+}
+
+impl Jitter {
+    /// Applies the jitter to a delay
+    pub fn apply(&self, delay: Duration) -> Duration {
+        match self {
+            Jitter::None => delay,
+            Jitter::Full => delay.mul_f64(crate::util::random_fraction()),
+            Jitter::Equal => {
+                let half = delay / 2;
+                half + half.mul_f64(crate::util::random_fraction())
+            }
+        }
+    }
 }
 
 impl PartialEq for RetryLimit {
@@ -63,16 +108,168 @@ impl PartialOrd<RetryLimit> for u64 {
 }
 
 impl RetryPolicy {
-    pub async fn wait(&self, count: u64) {
+    /// Returns the time to wait after the given attempt has failed,
+    /// as calculated by the backoff policy and capped by max_delay
+    pub fn delay(&self, count: u64) -> Duration {
         let t = (self.delay_time)(self, count);
-        let t = std::time::Duration::from_millis(t);
-        tokio::time::sleep(t).await;
+        match self.max_delay {
+            Some(max) => t.min(max),
+            None => t,
+        }
+    }
+
+    /// Waits for the delay after the given attempt, with jitter applied
+    pub async fn wait(&self, count: u64) {
+        tokio::time::sleep(self.jitter.apply(self.delay(count))).await;
+    }
+
+    /// Waits for a permit from the rate limiter, if the policy has one. Called before the first attempt
+    pub async fn acquire_permit(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+    }
+
+    /// Waits for a permit from the rate limiter, if the policy has one, for a retry made `elapsed` after the first
+    /// attempt started. Returns false, without waiting, if the permit would only be granted after the deadline.
+    /// Called after the delay before every retry
+    pub async fn acquire_permit_within(&self, elapsed: Duration) -> bool {
+        match (&self.rate_limiter, self.deadline) {
+            (None, _) => true,
+            (Some(rate_limiter), None) => {
+                rate_limiter.acquire().await;
+                true
+            }
+            (Some(rate_limiter), Some(deadline)) => {
+                rate_limiter.acquire_within(deadline.saturating_sub(elapsed)).await
+            }
+        }
+    }
+
+    /// Blocks the current thread until the rate limiter, if the policy has one, grants a permit
+    pub fn acquire_permit_blocking(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire_blocking();
+        }
+    }
+
+    /// Blocking counterpart of acquire_permit_within
+    pub fn acquire_permit_within_blocking(&self, elapsed: Duration) -> bool {
+        match (&self.rate_limiter, self.deadline) {
+            (None, _) => true,
+            (Some(rate_limiter), None) => {
+                rate_limiter.acquire_blocking();
+                true
+            }
+            (Some(rate_limiter), Some(deadline)) => {
+                rate_limiter.acquire_within_blocking(deadline.saturating_sub(elapsed))
+            }
+        }
+    }
+
+    /// Blocks the current thread for the delay after the given attempt, with jitter applied
+    pub fn wait_blocking(&self, count: u64) {
+        std::thread::sleep(self.jitter.apply(self.delay(count)));
     }
 
     pub fn can_retry(&self, count: u64) -> bool {
         count < self.limit
     }
 
+    /// Returns an iterator over the delays this policy would wait after each failed attempt, starting with attempt 1.
+    /// Respects the limit, max_delay and deadline; the time spent executing attempts is not included.
+    /// Delays are shown before jitter is applied, so they are the longest the policy would wait.
+    ///
+    /// The iterator is unbounded for unlimited policies without a deadline, so use take() in that case
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// for step in policy.schedule().take(5) {
+    ///     println!("attempt {} failed, waiting {:?} (total {:?})", step.attempt, step.delay, step.elapsed);
+    /// }
+    /// ```
+    pub fn schedule(&self) -> Schedule<'_> {
+        Schedule {
+            policy: self,
+            attempt: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Returns the name of the backoff policy if it is one of the built-in backoffs
+    pub fn backoff_name(&self) -> Option<&'static str> {
+        self.backoff.map(|kind| kind.name())
+    }
+
+    /// Checks both the limit and the deadline: returns true if another attempt may be made after
+    /// `count` attempts have been made in `elapsed` time
+    pub fn should_retry(&self, count: u64, elapsed: Duration) -> bool {
+        if !self.can_retry(count) {
+            return false;
+        }
+        match self.deadline {
+            None => true,
+            Some(deadline) => elapsed.saturating_add(self.delay(count)) <= deadline,
+        }
+    }
+
+    /// Checks the policy for settings that would make it misbehave at runtime:
+    ///
+    /// - a limit of zero attempts
+    /// - an unlimited policy with no delay, which would retry in a hot loop
+    /// - a max_delay smaller than the base_delay
+    /// - a deadline that ends before the first retry could start
+    /// - a backoff that overflows before the limit is reached (without a max_delay to cap it)
+    pub fn validate(&self) -> Result<(), RetryPolicyBuilderError> {
+        let mut violations = Vec::new();
+
+        if self.limit == 0 {
+            violations.push(PolicyViolation::ZeroLimit);
+        }
+
+        if self.limit == RetryLimit::Unlimited && self.base_delay.is_zero() {
+            violations.push(PolicyViolation::HotLoop);
+        }
+
+        if let Some(max_delay) = self.max_delay
+            && max_delay < self.base_delay
+        {
+            violations.push(PolicyViolation::MaxDelayBelowBaseDelay {
+                max_delay,
+                base_delay: self.base_delay,
+            });
+        }
+
+        if let Some(deadline) = self.deadline
+            && self.can_retry(1)
+            && deadline < self.delay(1)
+        {
+            violations.push(PolicyViolation::DeadlineBeforeFirstRetry {
+                deadline,
+                first_delay: self.delay(1),
+            });
+        }
+
+        if self.max_delay.is_none() {
+            /* the built-in backoffs saturate rather than overflow. Any backoff that reaches Duration::MAX
+            within the first OVERFLOW_PROBE_ATTEMPTS retries is treated as overflowing */
+            const OVERFLOW_PROBE_ATTEMPTS: u64 = 128;
+            let overflow = (1..=OVERFLOW_PROBE_ATTEMPTS)
+                .take_while(|attempt| self.can_retry(*attempt))
+                .find(|attempt| (self.delay_time)(self, *attempt) == Duration::MAX);
+            if let Some(attempt) = overflow {
+                violations.push(PolicyViolation::DelayOverflow { attempt });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(RetryPolicyBuilderError { violations })
+        }
+    }
+
     /// Runs a function against the given policy
     pub async fn call<Func, RetType, ErrType>(
         &self,
@@ -85,13 +282,19 @@ impl RetryPolicy {
             policy: crate::util::OwnedOrRef::Ref(self), /* Ref here to avoid consuming a policy we may want to use repeatedly */
             count: 0,
             function: Box::new(&executor),
+            timeout: None,
+            bulkhead: None,
         }.run().await
     }
 
-    /// Runs a function against the given policy
-    pub async fn call_closure<RetType: Send + Sync, ErrType: Send + Sync>(
+    /// Runs an async closure against the given policy.
+    /// The closure may mutate its captured state between attempts.
+    ///
+    /// Any ```FnMut() -> impl Future<Output = RetryResult<T, E>>``` is also accepted. The future returned by call_closure
+    /// is Send when such a closure's futures are, so unlike with an async closure it can be passed to tokio::spawn
+    pub async fn call_closure<RetType, ErrType>(
         &self,
-        f: impl AsyncFn() -> RetryResult<RetType, ErrType> + Send + Sync,
+        f: impl AsyncFnMut() -> RetryResult<RetType, ErrType>,
     ) -> Result<RetType, ErrType> {
         ClosureRetryer {
             policy: crate::util::OwnedOrRef::Ref(self), /* Ref here to avoid consuming a policy we may want to use repeatedly */
@@ -100,16 +303,175 @@ impl RetryPolicy {
         }.run().await
     }
 
+    /// Submits a batch of items, re-submitting only the items that should be retried. See eztry::batch::retry_batch
+    ///
+    /// # Panics
+    ///
+    /// If submit returns a different number of results than the items it was given
+    pub async fn call_batch<I, RetType, ErrType>(
+        &self,
+        items: Vec<I>,
+        submit: impl AsyncFnMut(&[I]) -> Vec<RetryResult<RetType, ErrType>>,
+    ) -> Vec<Result<RetType, ErrType>> {
+        crate::batch::retry_batch(self, items, submit).await
+    }
+
+    /// Prepares the executors to be retried concurrently with this policy, with at most limit of them running at once
+    /// (a limit of 0 is treated as 1). See eztry::concurrent::Concurrent
+    pub fn concurrent<I: IntoIterator>(&self, executors: I, limit: usize) -> Concurrent<'_, stream::Iter<I::IntoIter>> {
+        Concurrent::new(self, stream::iter(executors), limit)
+    }
+
+    /// Like concurrent, for executors produced by a stream. The stream is only polled when there is room for another executor
+    pub fn concurrent_stream<S: Stream>(&self, executors: S, limit: usize) -> Concurrent<'_, S> {
+        Concurrent::new(self, executors, limit)
+    }
+
+    /// Consumes a stream that can fail part of the way through, recreating it from the last item it yielded after
+    /// errors matching retry_if. See eztry::stream::resume_stream
+    pub fn resume_stream<'a, T, E, S, F, P>(
+        &'a self,
+        factory: F,
+        retry_if: P,
+    ) -> impl Stream<Item = Result<T, E>> + 'a
+    where
+        T: Clone + 'a,
+        E: 'a,
+        S: Stream<Item = Result<T, E>> + 'a,
+        F: FnMut(Option<&T>) -> S + 'a,
+        P: FnMut(&E) -> bool + 'a,
+    {
+        crate::stream::resume_stream(self, factory, retry_if)
+    }
+
+    /// Runs the probe until it is ready or fails, waiting this policy's delay after each Pending check.
+    /// See eztry::poll::poll_until
+    pub async fn poll_until<T, E>(
+        &self,
+        probe: impl AsyncFnMut() -> PollResult<T, E>,
+    ) -> Result<T, PollError<E>> {
+        crate::poll::poll_until(self, probe).await
+    }
+
+    /// Runs a blocking executor against the given policy, sleeping the current thread between attempts
+    pub fn call_blocking<Func, RetType, ErrType>(&self, executor: Func) -> Result<RetType, ErrType>
+    where
+        Func: BlockingExecutor<RetType, ErrType>,
+    {
+        self.call_closure_blocking(|| executor.execute())
+    }
+
+    /// Runs a blocking closure against the given policy, sleeping the current thread between attempts.
+    /// The closure may mutate its captured state between attempts
+    pub fn call_closure_blocking<RetType, ErrType>(
+        &self,
+        f: impl FnMut() -> RetryResult<RetType, ErrType>,
+    ) -> Result<RetType, ErrType> {
+        BlockingRetryer {
+            policy: crate::util::OwnedOrRef::Ref(self),
+            count: 0,
+            function: f,
+        }.run()
+    }
+
     pub fn builder() -> RetryPolicyBuilder {
         RetryPolicyBuilder::new()
     }
 }
 
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("limit", &self.limit)
+            .field("base_delay", &self.base_delay)
+            .field("backoff", &self.backoff_name().unwrap_or("custom"))
+            .field("max_delay", &self.max_delay)
+            .field("deadline", &self.deadline)
+            .field("jitter", &self.jitter)
+            .field("rate_limiter", &self.rate_limiter)
+            .finish()
+    }
+}
+
+/// Describes the policy in words, e.g. "up to 5 attempts, exponential backoff from 100ms, capped at 5s"
+impl Display for RetryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.limit {
+            RetryLimit::Unlimited => write!(f, "unlimited attempts")?,
+            RetryLimit::Limited(1) => write!(f, "up to 1 attempt")?,
+            RetryLimit::Limited(n) => write!(f, "up to {n} attempts")?,
+        }
+        write!(
+            f,
+            ", {} backoff from {:?}",
+            self.backoff_name().unwrap_or("custom"),
+            self.base_delay
+        )?;
+        match self.jitter {
+            Jitter::None => {}
+            Jitter::Full => write!(f, " with full jitter")?,
+            Jitter::Equal => write!(f, " with equal jitter")?,
+        }
+        if let Some(max_delay) = self.max_delay {
+            write!(f, ", capped at {max_delay:?}")?;
+        }
+        if let Some(deadline) = self.deadline {
+            write!(f, ", within {deadline:?}")?;
+        }
+        if self.rate_limiter.is_some() {
+            write!(f, ", rate limited")?;
+        }
+        Ok(())
+    }
+}
+
+/// A single step of a policy's schedule, see RetryPolicy::schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduledDelay {
+    /// The attempt that failed before this delay
+    pub attempt: u64,
+    /// The time waited before the next attempt
+    pub delay: Duration,
+    /// The total time waited so far, including this delay
+    pub elapsed: Duration,
+}
+
+/// Iterator over the delays of a RetryPolicy, see RetryPolicy::schedule
+pub struct Schedule<'a> {
+    policy: &'a RetryPolicy,
+    attempt: u64,
+    elapsed: Duration,
+}
+
+impl Iterator for Schedule<'_> {
+    type Item = ScheduledDelay;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let attempt = self.attempt + 1;
+        if !self.policy.should_retry(attempt, self.elapsed) {
+            return None;
+        }
+        let delay = self.policy.delay(attempt);
+        self.attempt = attempt;
+        self.elapsed = self.elapsed.saturating_add(delay);
+        Some(ScheduledDelay {
+            attempt,
+            delay,
+            elapsed: self.elapsed,
+        })
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct RetryPolicyBuilder {
     limit: Option<RetryLimit>,
-    base_delay: Option<u64>,
+    base_delay: Option<Duration>,
     backoff_policy: Option<BackoffPolicy>,
+    backoff: Option<BackoffKind>,
+    max_delay: Option<Duration>,
+    deadline: Option<Duration>,
+    jitter: Option<Jitter>,
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl RetryPolicyBuilder {
@@ -123,14 +485,19 @@ impl RetryPolicyBuilder {
     /// Creates a new RetryPolicyBuilder with default values. Default Values:
     ///
     /// - limit: Unlimited
-    /// - base_delay: 1000
+    /// - base_delay: 1000ms
     /// - backoff_policy: constant_backoff
     #[inline]
     pub fn new_with_defaults() -> Self {
         Self {
             limit: Some(RetryLimit::Unlimited),
-            base_delay: Some(1000),
+            base_delay: Some(Duration::from_millis(1000)),
             backoff_policy: Some(constant_backoff),
+            backoff: Some(BackoffKind::Constant),
+            max_delay: None,
+            deadline: None,
+            jitter: None,
+            rate_limiter: None,
         }
     }
 
@@ -143,28 +510,91 @@ impl RetryPolicyBuilder {
         self
     }
 
+    /// Sets the base delay for the RetryPolicy, in milliseconds. Shorthand for base_delay_duration
+    #[inline]
+    pub fn base_delay(self, base_delay: u64) -> Self {
+        self.base_delay_duration(Duration::from_millis(base_delay))
+    }
+
     /// Sets the base delay for the RetryPolicy.
-    /// Base delay is the time (in milliseconds) to wait before retrying a function.
+    /// Base delay is the time to wait before retrying a function.
     /// Does not apply to first attempt.
     /// Subsequent attempts will have their delay calculated by the backoff_policy
     #[inline]
-    pub fn base_delay(mut self, base_delay: u64) -> Self {
+    pub fn base_delay_duration(mut self, base_delay: Duration) -> Self {
         self.base_delay = Some(base_delay);
         self
     }
 
     /// Sets the backoff policy for the RetryPolicy.
     /// The backoff policy is a function that takes the RetryPolicy and the current attempt number
-    /// and returns the time (in milliseconds) to wait before retrying the function
+    /// and returns the time to wait before retrying the function
     /// Is called after the previous attempt has failed
+    ///
+    /// The policy describes the function as a custom backoff; use backoff to set a built-in backoff by name
     #[inline]
-    pub fn backoff_policy(mut self, backoff_policy: fn(&RetryPolicy, u64) -> u64) -> Self {
+    pub fn backoff_policy(mut self, backoff_policy: BackoffPolicy) -> Self {
         self.backoff_policy = Some(backoff_policy);
+        self.backoff = None;
+        self
+    }
+
+    /// Sets one of the built-in backoff policies. Unlike backoff_policy, the policy can describe and serialize it by name
+    #[inline]
+    pub fn backoff(mut self, backoff: BackoffKind) -> Self {
+        self.backoff_policy = Some(backoff.backoff_policy());
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Sets the maximum delay between attempts, in milliseconds. Shorthand for max_delay_duration
+    #[inline]
+    pub fn max_delay(self, max_delay: u64) -> Self {
+        self.max_delay_duration(Duration::from_millis(max_delay))
+    }
+
+    /// Sets the maximum delay between attempts.
+    /// Delays calculated by the backoff_policy are capped to this value. Optional, uncapped by default
+    #[inline]
+    pub fn max_delay_duration(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Sets the deadline for all attempts, in milliseconds. Shorthand for deadline_duration
+    #[inline]
+    pub fn deadline(self, deadline: u64) -> Self {
+        self.deadline_duration(Duration::from_millis(deadline))
+    }
+
+    /// Sets the deadline for all attempts, measured from the start of the first attempt.
+    /// A retry is not started if its delay would end after the deadline; the last error is returned instead.
+    /// Optional, no deadline by default
+    #[inline]
+    pub fn deadline_duration(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the jitter applied to each delay. Optional, Jitter::None by default
+    #[inline]
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = Some(jitter);
+        self
+    }
+
+    /// Sets a rate limiter that every attempt acquires a permit from, including the first.
+    /// Share a clone of the limiter between policies to pace all of their attempts together. Optional, unlimited by default
+    #[inline]
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
         self
     }
 
     /// Builds a RetryPolicy with the given parameters from the builder
     ///
+    /// The policy is not validated, see try_build
+    ///
     /// # Panics
     ///
     /// Panics if any of the required fields are not set. To avoid panics, use
@@ -180,6 +610,11 @@ impl RetryPolicyBuilder {
             delay_time: self
                 .backoff_policy
                 .expect("delay_time be set before calling build"),
+            backoff: self.backoff,
+            max_delay: self.max_delay,
+            deadline: self.deadline,
+            jitter: self.jitter.unwrap_or_default(),
+            rate_limiter: self.rate_limiter,
         }
     }
 
@@ -187,65 +622,163 @@ impl RetryPolicyBuilder {
     /// If any required fields are not set, the default values will be used.
     /// Default Values:
     /// - limit: Unlimited
-    /// - base_delay: 1000
+    /// - base_delay: 1000ms
     /// - backoff_policy: constant_backoff
     ///
-    /// Unlike build, this method will not panic if any required fields are not set
+    /// Unlike build, this method will not panic if any required fields are not set.
+    /// The policy is not validated, see try_build
     #[inline]
     pub fn build_with_defaults(self) -> RetryPolicy {
         RetryPolicy {
             limit: self.limit.unwrap_or(RetryLimit::Unlimited),
-            base_delay: self.base_delay.unwrap_or(1000),
+            base_delay: self.base_delay.unwrap_or(Duration::from_millis(1000)),
             delay_time: self.backoff_policy.unwrap_or(constant_backoff),
+            backoff: match self.backoff_policy {
+                Some(_) => self.backoff,
+                None => Some(BackoffKind::Constant),
+            },
+            max_delay: self.max_delay,
+            deadline: self.deadline,
+            jitter: self.jitter.unwrap_or_default(),
+            rate_limiter: self.rate_limiter,
         }
     }
 
     /// Builds a RetryPolicy with the given parameters from the builder.
-    /// Any missing fields are added to the error returned
+    /// Any missing fields are added to the error returned. If all fields are set,
+    /// the policy is validated with RetryPolicy::validate and any violations are returned
     #[inline]
     pub fn try_build(self) -> Result<RetryPolicy, RetryPolicyBuilderError> {
-        let mut error = RetryPolicyBuilderError {
-            missing_base_delay: false,
-            missing_backoff_policy: false,
-            missing_limit: false,
-        };
-
-        let mut missing_any = false;
+        let mut violations = Vec::new();
         if self.limit.is_none() {
-            error.missing_limit = true;
-            missing_any = true;
+            violations.push(PolicyViolation::MissingLimit);
         }
         if self.base_delay.is_none() {
-            error.missing_base_delay = true;
-            missing_any = true;
+            violations.push(PolicyViolation::MissingBaseDelay);
         }
         if self.backoff_policy.is_none() {
-            error.missing_backoff_policy = true;
-            missing_any = true;
+            violations.push(PolicyViolation::MissingBackoffPolicy);
         }
 
-        if missing_any {
-            return Err(error);
+        match (self.limit, self.base_delay, self.backoff_policy) {
+            (Some(limit), Some(base_delay), Some(delay_time)) => {
+                let policy = RetryPolicy {
+                    limit,
+                    base_delay,
+                    delay_time,
+                    backoff: self.backoff,
+                    max_delay: self.max_delay,
+                    deadline: self.deadline,
+                    jitter: self.jitter.unwrap_or_default(),
+                    rate_limiter: self.rate_limiter,
+                };
+                policy.validate()?;
+                Ok(policy)
+            }
+            _ => Err(RetryPolicyBuilderError { violations }),
         }
+    }
+}
 
-        Ok(RetryPolicy {
-            limit: self.limit.unwrap(),
-            base_delay: self.base_delay.unwrap(),
-            delay_time: self.backoff_policy.unwrap(),
-        })
+/// A single reason a RetryPolicy could not be built
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    MissingLimit,
+    MissingBaseDelay,
+    MissingBackoffPolicy,
+    /// The limit allows no attempts at all
+    ZeroLimit,
+    /// Unlimited attempts with no delay between them
+    HotLoop,
+    /// The backoff policy overflows at this attempt
+    DelayOverflow { attempt: u64 },
+    /// The deadline ends before the first retry could start
+    DeadlineBeforeFirstRetry {
+        deadline: Duration,
+        first_delay: Duration,
+    },
+    MaxDelayBelowBaseDelay {
+        max_delay: Duration,
+        base_delay: Duration,
+    },
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::MissingLimit => write!(f, "limit is not set"),
+            PolicyViolation::MissingBaseDelay => write!(f, "base_delay is not set"),
+            PolicyViolation::MissingBackoffPolicy => write!(f, "backoff_policy is not set"),
+            PolicyViolation::ZeroLimit => write!(f, "limit must allow at least one attempt"),
+            PolicyViolation::HotLoop => {
+                write!(f, "unlimited attempts with a base_delay of 0 would retry in a hot loop")
+            }
+            PolicyViolation::DelayOverflow { attempt } => write!(
+                f,
+                "backoff delay overflows at attempt {attempt}; set a max_delay or a lower limit"
+            ),
+            PolicyViolation::DeadlineBeforeFirstRetry {
+                deadline,
+                first_delay,
+            } => write!(
+                f,
+                "deadline of {deadline:?} is shorter than the first delay of {first_delay:?}"
+            ),
+            PolicyViolation::MaxDelayBelowBaseDelay {
+                max_delay,
+                base_delay,
+            } => write!(
+                f,
+                "max_delay of {max_delay:?} is less than base_delay of {base_delay:?}"
+            ),
+        }
     }
 }
 
-/// Error returned when a RetryPolicyBuilder is missing required fields
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Error returned when a RetryPolicyBuilder is missing required fields or would build an invalid policy
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct RetryPolicyBuilderError {
-    missing_limit: bool,
-    missing_base_delay: bool,
-    missing_backoff_policy: bool,
+    violations: Vec<PolicyViolation>,
+}
+
+impl RetryPolicyBuilderError {
+    /// All the reasons the policy could not be built
+    pub fn violations(&self) -> &[PolicyViolation] {
+        &self.violations
+    }
+
+    pub fn missing_limit(&self) -> bool {
+        self.violations.contains(&PolicyViolation::MissingLimit)
+    }
+
+    pub fn missing_base_delay(&self) -> bool {
+        self.violations.contains(&PolicyViolation::MissingBaseDelay)
+    }
+
+    pub fn missing_backoff_policy(&self) -> bool {
+        self.violations
+            .contains(&PolicyViolation::MissingBackoffPolicy)
+    }
+}
+
+impl Display for RetryPolicyBuilderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid retry policy: ")?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{violation}")?;
+        }
+        Ok(())
+    }
 }
 
+impl std::error::Error for RetryPolicyBuilderError {}
+
 /// Utility trait to make async closures retryable.
-/// This trait is implemented for all async closures that return a RetryResult.
+/// This trait is implemented for all async closures that return a RetryResult, including closures that
+/// mutate their captured state between attempts, and plain closures returning a future (```|| async { .. }```).
 ///
 /// Can be used to retry the closure immediately with a policy or the default policy.
 /// See: eztry::policy::DEFAULT_POLICY
@@ -274,7 +807,7 @@ pub trait Retryable<T, E> {
     ///
     /// Result<T,E> compatible with the RetryResult<T,E> returned by the closure
     ///
-    async fn retry(&self, policy: &RetryPolicy) -> Result<T, E>;
+    async fn retry(&mut self, policy: &RetryPolicy) -> Result<T, E>;
 
     /// Provided by the eztry::Retryable trait, re-exported in prelude.
     /// Retry the closure with the default policy. See: eztry::policy::DEFAULT_POLICY
@@ -298,21 +831,19 @@ pub trait Retryable<T, E> {
     ///
     /// Result<T,E> compatible with the RetryResult<T,E> returned by the closure
     ///
-    async fn retry_with_default_policy(&self) -> Result<T, E>;
+    async fn retry_with_default_policy(&mut self) -> Result<T, E>;
 }
 
 impl<F, T, E> Retryable<T, E> for F
 where
-    F: AsyncFn() -> RetryResult<T, E> + Send + Sync,
-    T: Send + Sync,
-    E: Send + Sync,
+    F: AsyncFnMut() -> RetryResult<T, E>,
 {
-    async fn retry(&self, policy: &RetryPolicy) -> Result<T, E> {
+    async fn retry(&mut self, policy: &RetryPolicy) -> Result<T, E> {
         policy.call_closure(self).await
     }
 
-    async fn retry_with_default_policy(&self) -> Result<T, E> {
-        let policy = global::get_default_policy();
-        policy.call_closure(self).await
+    async fn retry_with_default_policy(&mut self) -> Result<T, E> {
+        let policy = global::current_default_policy();
+        policy.as_ref().call_closure(self).await
     }
 }
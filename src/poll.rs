@@ -0,0 +1,85 @@
+use crate::policy::RetryPolicy;
+use crate::retry_result::RetryResult;
+use crate::retryer::AttemptLoop;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// The outcome of one check of a poll_until probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollResult<T, E> {
+    /// The awaited resource or job is ready, stop polling
+    Ready(T),
+    /// Not ready yet, check again after the policy's delay
+    Pending,
+    /// Waiting can't succeed, stop polling
+    Failed(E),
+}
+
+/// Why poll_until stopped without the probe becoming ready
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollError<E> {
+    /// The probe returned Failed
+    Failed(E),
+    /// The probe was still pending when the policy's limit or deadline was reached
+    TimedOut { attempts: u64, elapsed: Duration },
+}
+
+impl<E: Display> Display for PollError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PollError::Failed(e) => write!(f, "{e}"),
+            PollError::TimedOut { attempts, elapsed } => {
+                write!(f, "still pending after {attempts} checks ({elapsed:?})")
+            }
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for PollError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PollError::Failed(e) => Some(e),
+            PollError::TimedOut { .. } => None,
+        }
+    }
+}
+
+/// Runs the probe until it is ready or fails, waiting the policy's delay after each Pending check.
+/// Each check counts as an attempt against the policy's limit and deadline, and running out of either is
+/// reported as PollError::TimedOut rather than as an error of the probe
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let output = poll_until(&policy, async || match jobs.status(id).await {
+///     Ok(Status::Done(output)) => PollResult::Ready(output),
+///     Ok(Status::Running) => PollResult::Pending,
+///     Ok(Status::Cancelled) => PollResult::Failed(JobError::Cancelled),
+///     Err(e) => PollResult::Failed(e.into()),
+/// }).await?;
+/// ```
+pub async fn poll_until<T, E>(
+    policy: &RetryPolicy,
+    mut probe: impl AsyncFnMut() -> PollResult<T, E>,
+) -> Result<T, PollError<E>> {
+    let mut attempts = AttemptLoop::new(policy);
+    let result = loop {
+        attempts.acquire().await;
+        /* a pending check is retried, None telling it apart from a failure once the policy stops retrying */
+        let result = match probe().await {
+            PollResult::Ready(v) => RetryResult::Success(v),
+            PollResult::Pending => RetryResult::Retry(None),
+            PollResult::Failed(e) => RetryResult::Abort(Some(e)),
+        };
+        if let Some(result) = attempts.next(result).await {
+            break result;
+        }
+    };
+    result.map_err(|e| match e {
+        Some(e) => PollError::Failed(e),
+        None => PollError::TimedOut {
+            attempts: attempts.count(),
+            elapsed: attempts.elapsed(),
+        },
+    })
+}
@@ -0,0 +1,123 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Paces attempts across every policy (and every task) that shares it, using the generic cell rate algorithm:
+/// permits are spaced evenly over the period, with up to burst permits available at once after a quiet spell.
+///
+/// A policy with a rate limiter acquires a permit before every attempt, including the first. Clones share the same limit
+///
+/// # Example
+///
+/// ```rust, ignore
+/// /* at most 10 requests a second to the API, from any retry loop in the process */
+/// let limiter = RateLimiter::new(10, Duration::from_secs(1));
+/// let policy = RetryPolicy::builder()
+///     .limit(RetryLimit::Limited(5))
+///     .backoff_policy(exponential_backoff)
+///     .base_delay(100)
+///     .rate_limiter(limiter.clone())
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    burst: u32,
+    /// The theoretical arrival time: when the next permit would be granted if no burst were allowed
+    next: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    /// Allows permits at an average of permits per period, with a burst of the same size
+    ///
+    /// # Panics
+    ///
+    /// If permits is zero, or the period is too long to be added to an Instant (e.g. Duration::MAX)
+    pub fn new(permits: u32, period: Duration) -> Self {
+        assert!(permits > 0, "a rate limiter must allow at least one permit per period");
+        assert!(
+            Instant::now().checked_add(period).is_some(),
+            "the period of a rate limiter must be representable as an Instant"
+        );
+        RateLimiter {
+            interval: period / permits,
+            burst: permits,
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Sets how many permits can be granted at once after a quiet spell. A burst of 1 spaces every permit evenly
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Reserves the next permit, returning how long to wait before it may be used. If the wait would be longer
+    /// than max_wait, nothing is reserved and None is returned
+    fn reserve(&self, max_wait: Option<Duration>) -> Option<Duration> {
+        let now = Instant::now();
+        let tolerance = self.interval.saturating_mul(self.burst - 1);
+        let mut next = self.next.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let arrival = (*next).max(now);
+        let wait = arrival
+            .checked_sub(tolerance)
+            .map_or(Duration::ZERO, |allowed| allowed.saturating_duration_since(now));
+        if max_wait.is_some_and(|max_wait| wait > max_wait) {
+            return None;
+        }
+        *next = arrival + self.interval;
+        Some(wait)
+    }
+
+    /// Waits until a permit is available
+    pub async fn acquire(&self) {
+        if let Some(wait) = self.reserve(None) {
+            Self::sleep(wait).await;
+        }
+    }
+
+    /// Waits for a permit if one is available within max_wait. Returns false without waiting, and without
+    /// taking a permit, otherwise
+    pub async fn acquire_within(&self, max_wait: Duration) -> bool {
+        match self.reserve(Some(max_wait)) {
+            Some(wait) => {
+                Self::sleep(wait).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Blocks the current thread until a permit is available
+    pub fn acquire_blocking(&self) {
+        if let Some(wait) = self.reserve(None) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Blocking counterpart of acquire_within
+    pub fn acquire_within_blocking(&self, max_wait: Duration) -> bool {
+        match self.reserve(Some(max_wait)) {
+            Some(wait) => {
+                std::thread::sleep(wait);
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn sleep(wait: Duration) {
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Debug for RateLimiter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("interval", &self.interval)
+            .field("burst", &self.burst)
+            .finish()
+    }
+}
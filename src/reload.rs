@@ -0,0 +1,158 @@
+use crate::config::{PoliciesConfig, PolicyConfigError};
+use std::fmt::{Display, Formatter};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Watches a JSON file containing a PoliciesConfig and applies it to the global policies whenever it changes.
+///
+/// The file is polled for changes to its contents. Each change is parsed and validated as a whole;
+/// invalid files are reported to the error handler and the currently applied policies are kept.
+/// An error is reported once, and again only after it changes or the file has been applied successfully,
+/// so a missing file does not report on every poll.
+///
+/// Named policies removed from the file are unregistered and, if the default policy is removed from the
+/// file, the global default policy is reset. Policies registered by other means are left untouched.
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let handle = PolicyWatcher::new("./retry_policies.json")
+///     .interval(Duration::from_secs(5))
+///     .on_error(|e| log::warn!("retry policies not reloaded: {e}"))
+///     .spawn();
+/// ```
+pub struct PolicyWatcher {
+    path: PathBuf,
+    interval: Duration,
+    on_error: Box<dyn Fn(&ReloadError) + Send + Sync>,
+    applied: PoliciesConfig,
+    last_contents: Option<u64>,
+    last_error: Option<String>,
+}
+
+impl PolicyWatcher {
+    /// Creates a watcher for the given file. Defaults:
+    ///
+    /// - interval: 1 second
+    /// - on_error: ignores the error. Install a handler to log reload failures
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(1),
+            on_error: Box::new(|_| {}),
+            applied: PoliciesConfig::default(),
+            last_contents: None,
+            last_error: None,
+        }
+    }
+
+    /// Sets how often the file is checked for changes
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the handler called when the file cannot be read, parsed or validated
+    pub fn on_error(mut self, on_error: impl Fn(&ReloadError) + Send + Sync + 'static) -> Self {
+        self.on_error = Box::new(on_error);
+        self
+    }
+
+    /// Reads, validates and applies the file once, returning the error instead of passing it to the error handler
+    pub async fn reload(&mut self) -> Result<&PoliciesConfig, ReloadError> {
+        let contents = self.read_contents().await?;
+        self.apply_contents(&contents)?;
+        Ok(&self.applied)
+    }
+
+    /// Checks the file once and applies it if its contents changed since the last check.
+    /// Errors are passed to the error handler
+    pub async fn poll(&mut self) {
+        let contents = match self.read_contents().await {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.last_contents = None;
+                self.report(e);
+                return;
+            }
+        };
+
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.last_contents == Some(hash) {
+            return;
+        }
+        self.last_contents = Some(hash);
+
+        match self.apply_contents(&contents) {
+            Ok(()) => self.last_error = None,
+            Err(e) => self.report(e),
+        }
+    }
+
+    /// Loads the file immediately, then keeps polling it in a background task for as long as the handle is not aborted
+    pub fn spawn(mut self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.poll().await;
+                tokio::time::sleep(self.interval).await;
+            }
+        })
+    }
+
+    async fn read_contents(&self) -> Result<String, ReloadError> {
+        tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(ReloadError::Io)
+    }
+
+    /// Policies are only re-applied when the parsed config differs from the last applied one
+    fn apply_contents(&mut self, contents: &str) -> Result<(), ReloadError> {
+        let config: PoliciesConfig = serde_json::from_str(contents).map_err(ReloadError::Parse)?;
+        if config != self.applied {
+            config
+                .apply_replacing(&self.applied)
+                .map_err(ReloadError::Invalid)?;
+            self.applied = config;
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, error: ReloadError) {
+        let message = error.to_string();
+        if self.last_error.as_ref() != Some(&message) {
+            (self.on_error)(&error);
+            self.last_error = Some(message);
+        }
+    }
+}
+
+/// Error reported by a PolicyWatcher. The previously applied policies remain in effect
+#[derive(Debug)]
+pub enum ReloadError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Invalid(PolicyConfigError),
+}
+
+impl Display for ReloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReloadError::Io(e) => write!(f, "could not read policy file: {e}"),
+            ReloadError::Parse(e) => write!(f, "could not parse policy file: {e}"),
+            ReloadError::Invalid(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReloadError::Io(e) => Some(e),
+            ReloadError::Parse(e) => Some(e),
+            ReloadError::Invalid(e) => Some(e),
+        }
+    }
+}
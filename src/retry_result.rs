@@ -8,6 +8,32 @@ pub enum RetryResult<T, E> {
 unsafe impl<T,E> Send for RetryResult<T,E> {}
 unsafe impl<T,E> Sync for RetryResult<T,E> {}
 
+impl<T, E> RetryResult<T, E> {
+    /// Converts a Result, retrying the errors that match the predicate and aborting on all others
+    ///
+    /// # Example
+    ///
+    /// ```rust, ignore
+    /// let res = RetryResult::retry_if(client.get(url).await, |e| e.is_timeout());
+    /// ```
+    pub fn retry_if(result: Result<T, E>, predicate: impl FnOnce(&E) -> bool) -> Self {
+        match result {
+            Ok(t) => RetryResult::Success(t),
+            Err(e) if predicate(&e) => RetryResult::Retry(e),
+            Err(e) => RetryResult::Abort(e),
+        }
+    }
+
+    /// Converts a Result, aborting on the errors that match the predicate and retrying all others
+    pub fn abort_if(result: Result<T, E>, predicate: impl FnOnce(&E) -> bool) -> Self {
+        match result {
+            Ok(t) => RetryResult::Success(t),
+            Err(e) if predicate(&e) => RetryResult::Abort(e),
+            Err(e) => RetryResult::Retry(e),
+        }
+    }
+}
+
 impl<T, E> From<RetryResult<T, E>> for Result<T, E> {
     fn from(r: RetryResult<T, E>) -> Self {
         match r {
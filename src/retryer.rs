@@ -1,45 +1,210 @@
 use crate::policy::RetryPolicy;
-use crate::prelude::AsyncFunction;
+use crate::prelude::{AsyncFunction, Executor};
 use crate::retry_result::RetryResult;
+use crate::bulkhead::{Bulkhead, BulkheadRejected, OnRejected};
+use crate::timeout::{OnTimeout, TimeoutError};
 use crate::{util};
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The future a prepared retry is turned into when it is awaited directly
+pub type RetryFuture<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'a>>;
 
 pub struct Retryer<'a, T, E> {
     pub(crate) policy: util::OwnedOrRef<'a, RetryPolicy>,
     pub(crate) count: u64, /* not pub, meant to be internal only */
     pub(crate) function: AsyncFunction<'a, T, E>,
+    /// Timeout of each attempt, and the conversion of a timeout into the executor's error
+    pub(crate) timeout: Option<(Duration, OnTimeout<E>)>,
+    /// Bulkhead each attempt takes a slot of, and the conversion of a rejection into the executor's error
+    pub(crate) bulkhead: Option<(Bulkhead, OnRejected<E>)>,
 }
 
 impl<T, E> Retryer<'_, T, E> {
     pub async fn run(&mut self) -> Result<T, E> {
-        let f = &self.function;
-        let policy = self.policy.as_ref();
-        self.count = 0;
-        loop {
-            self.count += 1;
-            match f.execute().await {
-                RetryResult::Success(v) => return Ok(v),
-                RetryResult::Abort(v) => return Err(v),
-                RetryResult::Retry(e) => {
-                    if self.count >= policy.limit {
-                        return Err(e);
-                    }
-                    policy.wait(self.count).await
-                }
+        let mut attempts = AttemptLoop::new(self.policy.as_ref());
+        let result = loop {
+            attempts.acquire().await;
+            let result = guard_attempt(self.function.execute(), &self.bulkhead, self.timeout)
+                .await
+                .unwrap_or_else(RetryResult::Abort);
+            if let Some(result) = attempts.next(result).await {
+                break result;
             }
-        }
+        };
+        self.count = attempts.count();
+        result
     }
 
     pub fn set_policy(&mut self, policy: RetryPolicy) {
         self.policy = util::OwnedOrRef::Owned(policy);
     }
+
+    /// Sets a timeout for each attempt. An attempt that times out is retried like any other Retry,
+    /// with on_timeout converting the TimeoutError into the executor's error type
+    pub fn set_timeout_with(&mut self, timeout: Duration, on_timeout: OnTimeout<E>) {
+        self.timeout = Some((timeout, on_timeout));
+    }
+
+    /// Runs each attempt in a slot of the bulkhead. An attempt rejected by a full bulkhead ends the retry,
+    /// with on_rejected converting the BulkheadRejected into the executor's error type
+    pub fn set_bulkhead_with(&mut self, bulkhead: Bulkhead, on_rejected: OnRejected<E>) {
+        self.bulkhead = Some((bulkhead, on_rejected));
+    }
+
     pub fn count(&self) -> u64 {
         self.count
     }
 }
 
+impl<T, E: From<TimeoutError>> Retryer<'_, T, E> {
+    /// Sets a timeout for each attempt. See eztry::timeout::with_timeout
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.set_timeout_with(timeout, E::from);
+    }
+}
+
+impl<T, E: From<BulkheadRejected>> Retryer<'_, T, E> {
+    /// Runs each attempt in a slot of the bulkhead. See eztry::bulkhead::with_bulkhead
+    pub fn set_bulkhead(&mut self, bulkhead: Bulkhead) {
+        self.set_bulkhead_with(bulkhead, E::from);
+    }
+}
+
+/// Runs a single attempt in a slot of the bulkhead, bounded by the timeout, as Retryer and Pipeline do for each attempt.
+/// A timeout is a Retry converted into the executor's error type. Returns the converted rejection as an Err if the
+/// bulkhead rejected the attempt, so callers can tell that it never ran
+pub(crate) async fn guard_attempt<T, E>(
+    attempt: impl Future<Output = RetryResult<T, E>>,
+    bulkhead: &Option<(Bulkhead, OnRejected<E>)>,
+    timeout: Option<(Duration, OnTimeout<E>)>,
+) -> Result<RetryResult<T, E>, E> {
+    let _slot = match bulkhead {
+        None => None,
+        Some((bulkhead, on_rejected)) => Some(bulkhead.acquire().await.map_err(on_rejected)?),
+    };
+    Ok(match timeout {
+        None => attempt.await,
+        Some((timeout, on_timeout)) => match tokio::time::timeout(timeout, attempt).await {
+            Ok(result) => result,
+            Err(_) => RetryResult::Retry(on_timeout(TimeoutError { timeout })),
+        },
+    })
+}
+
+/// Awaiting a Retryer runs it, so ```executor.prepare().await``` is the same as ```executor.prepare().run().await```.
+/// The future can also be passed to select!, join! or FuturesUnordered
+impl<'a, T: Send + 'a, E: Send + 'a> IntoFuture for Retryer<'a, T, E> {
+    type Output = Result<T, E>;
+    type IntoFuture = RetryFuture<'a, T, E>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        Box::pin(async move { self.run().await })
+    }
+}
+
+/// A Retryer that owns its executor and policy, so it can be stored or moved into another task.
+/// Created with Executor::prepare_owned, or from an executor that is already shared with OwnedRetryer::new.
+///
+/// The default policy is resolved when the retryer is created, so a retryer prepared inside a with_policy
+/// scope keeps that policy after being spawned
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let mut retryer = Fetch::new(client, url).prepare_owned();
+/// retryer.set_policy(policy);
+/// let handle = retryer.spawn();
+/// /* ... */
+/// let page = handle.await.expect("retry task panicked")?;
+/// ```
+pub struct OwnedRetryer<T, E> {
+    pub(crate) policy: util::OwnedOrRef<'static, RetryPolicy>,
+    pub(crate) function: Arc<dyn Executor<T, E>>,
+    pub(crate) timeout: Option<(Duration, OnTimeout<E>)>,
+    pub(crate) bulkhead: Option<(Bulkhead, OnRejected<E>)>,
+}
+
+impl<T, E> OwnedRetryer<T, E> {
+    /// Prepares a shared executor to be retried with the default policy
+    pub fn new(executor: Arc<dyn Executor<T, E>>) -> Self {
+        OwnedRetryer {
+            policy: crate::global::current_default_policy(),
+            function: executor,
+            timeout: None,
+            bulkhead: None,
+        }
+    }
+
+    /// Runs the retry loop. The future owns everything it uses, so it is 'static, and Send when T and E are
+    pub async fn run(self) -> Result<T, E> {
+        Retryer {
+            policy: self.policy,
+            count: 0,
+            function: Box::new(&*self.function),
+            timeout: self.timeout,
+            bulkhead: self.bulkhead,
+        }
+        .run()
+        .await
+    }
+
+    /// Runs the retry loop in a new task on the current tokio runtime. See tokio::spawn
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime
+    pub fn spawn(self) -> tokio::task::JoinHandle<Result<T, E>>
+    where
+        T: Send + 'static,
+        E: Send + 'static,
+    {
+        tokio::spawn(self.run())
+    }
+
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = util::OwnedOrRef::Owned(policy);
+    }
+
+    /// Sets a timeout for each attempt. See Retryer::set_timeout_with
+    pub fn set_timeout_with(&mut self, timeout: Duration, on_timeout: OnTimeout<E>) {
+        self.timeout = Some((timeout, on_timeout));
+    }
+
+    /// Runs each attempt in a slot of the bulkhead. See Retryer::set_bulkhead_with
+    pub fn set_bulkhead_with(&mut self, bulkhead: Bulkhead, on_rejected: OnRejected<E>) {
+        self.bulkhead = Some((bulkhead, on_rejected));
+    }
+}
+
+impl<T, E: From<TimeoutError>> OwnedRetryer<T, E> {
+    /// Sets a timeout for each attempt. See eztry::timeout::with_timeout
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.set_timeout_with(timeout, E::from);
+    }
+}
+
+impl<T, E: From<BulkheadRejected>> OwnedRetryer<T, E> {
+    /// Runs each attempt in a slot of the bulkhead. See eztry::bulkhead::with_bulkhead
+    pub fn set_bulkhead(&mut self, bulkhead: Bulkhead) {
+        self.set_bulkhead_with(bulkhead, E::from);
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> IntoFuture for OwnedRetryer<T, E> {
+    type Output = Result<T, E>;
+    type IntoFuture = RetryFuture<'static, T, E>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
 pub struct ClosureRetryer<'a, T, E, F>
 where
-    F: AsyncFn() -> RetryResult<T, E> + Send + Sync,
+    F: AsyncFnMut() -> RetryResult<T, E>,
 {
     pub(crate) policy: util::OwnedOrRef<'a, RetryPolicy>,
     pub(crate) count: u64, /* not pub, meant to be internal only */
@@ -48,31 +213,193 @@ where
 
 impl<T, E, F> ClosureRetryer<'_, T, E, F>
 where
-    F: AsyncFn() -> RetryResult<T, E> + Send + Sync,
+    F: AsyncFnMut() -> RetryResult<T, E>,
 {
+    /// Prepares a closure to be retried with the default policy. Nothing runs until run() is called,
+    /// or the ClosureRetryer is awaited
+    pub fn new(function: F) -> Self {
+        ClosureRetryer {
+            policy: crate::global::current_default_policy(),
+            count: 0,
+            function,
+        }
+    }
+
     pub async fn run(mut self) -> Result<T, E> {
-        let f = &self.function;
-        let policy = self.policy.as_ref();
-        self.count = 0;
-        loop {
-            self.count += 1;
-            match f().await {
-                RetryResult::Success(v) => return Ok(v),
-                RetryResult::Abort(v) => return Err(v),
-                RetryResult::Retry(e) => {
-                    if self.count >= policy.limit {
-                        return Err(e);
-                    }
-                    policy.wait(self.count).await
-                }
+        let mut attempts = AttemptLoop::new(self.policy.as_ref());
+        let result = loop {
+            attempts.acquire().await;
+            let result = (self.function)().await;
+            if let Some(result) = attempts.next(result).await {
+                break result;
             }
+        };
+        self.count = attempts.count();
+        result
+    }
+
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = util::OwnedOrRef::Owned(policy);
+    }
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Awaiting a ClosureRetryer runs it. The future of an async closure can't be required to be Send,
+/// so unlike RetryFuture this one is not, and can't be passed to tokio::spawn
+impl<'a, T: 'a, E: 'a, F> IntoFuture for ClosureRetryer<'a, T, E, F>
+where
+    F: AsyncFnMut() -> RetryResult<T, E> + 'a,
+{
+    type Output = Result<T, E>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Result<T, E>> + 'a>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+/// Drives a retry loop whose attempts are written inline, as the code generated by #[retry] does.
+/// Each attempt's result is passed to next(), which either returns the final result or waits for the next attempt.
+/// acquire() should be awaited before each attempt, so that policies with a rate limiter are respected; the permit of
+/// a retry is reserved by next(), which returns the last error instead if it would only be granted after the deadline.
+///
+/// Unlike ClosureRetryer, nothing is borrowed by a closure across attempts, so the loop's future is Send
+/// whenever the attempts are, including inside #[async_trait] methods
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let mut attempts = AttemptLoop::new(&policy);
+/// let result = loop {
+///     attempts.acquire().await;
+///     let attempt = fetch(&client).await;
+///     if let Some(result) = attempts.next(attempt).await {
+///         break result;
+///     }
+/// };
+/// ```
+pub struct AttemptLoop<'a> {
+    policy: &'a RetryPolicy,
+    count: u64,
+    started: std::time::Instant,
+    /// Whether next() already waited for the permit of the coming attempt
+    permit: bool,
+}
+
+impl<'a> AttemptLoop<'a> {
+    pub fn new(policy: &'a RetryPolicy) -> Self {
+        Self {
+            policy,
+            count: 0,
+            started: std::time::Instant::now(),
+            permit: false,
         }
     }
 
+    /// Waits for a permit from the policy's rate limiter, if it has one and next() has not already reserved it.
+    /// Call before each attempt
+    pub async fn acquire(&mut self) {
+        if !std::mem::take(&mut self.permit) {
+            self.policy.acquire_permit().await
+        }
+    }
+
+    /// Blocking counterpart of acquire
+    pub fn acquire_blocking(&mut self) {
+        if !std::mem::take(&mut self.permit) {
+            self.policy.acquire_permit_blocking()
+        }
+    }
+
+    /// Records the result of an attempt. Returns the final result if the loop is finished, otherwise waits for the
+    /// policy's delay and the permit of the next attempt and returns None
+    pub async fn next<T, E>(&mut self, result: RetryResult<T, E>) -> Option<Result<T, E>> {
+        let e = match self.record(result) {
+            Ok(result) => return Some(result),
+            Err(e) => e,
+        };
+        self.policy.wait(self.count).await;
+        if !self.policy.acquire_permit_within(self.started.elapsed()).await {
+            return Some(Err(e));
+        }
+        self.permit = true;
+        None
+    }
+
+    /// Blocking counterpart of next, sleeping the current thread
+    pub fn next_blocking<T, E>(&mut self, result: RetryResult<T, E>) -> Option<Result<T, E>> {
+        let e = match self.record(result) {
+            Ok(result) => return Some(result),
+            Err(e) => e,
+        };
+        self.policy.wait_blocking(self.count);
+        if !self.policy.acquire_permit_within_blocking(self.started.elapsed()) {
+            return Some(Err(e));
+        }
+        self.permit = true;
+        None
+    }
+
+    /// Counts the attempt, and returns the final result, or the error of an attempt that will be retried
+    fn record<T, E>(&mut self, result: RetryResult<T, E>) -> Result<Result<T, E>, E> {
+        self.count += 1;
+        match result {
+            RetryResult::Success(v) => Ok(Ok(v)),
+            RetryResult::Abort(e) => Ok(Err(e)),
+            RetryResult::Retry(e) if !self.policy.should_retry(self.count, self.started.elapsed()) => Ok(Err(e)),
+            RetryResult::Retry(e) => Err(e),
+        }
+    }
+
+    /// Returns whether an attempt that fails now would be retried, i.e. whether next() would wait rather than
+    /// return the error of a Retry result
+    pub fn will_retry(&self) -> bool {
+        self.policy.should_retry(self.count + 1, self.started.elapsed())
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Time since the loop was created
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Retries a blocking closure, sleeping the current thread between attempts
+pub struct BlockingRetryer<'a, T, E, F>
+where
+    F: FnMut() -> RetryResult<T, E>,
+{
+    pub(crate) policy: util::OwnedOrRef<'a, RetryPolicy>,
+    pub(crate) count: u64, /* not pub, meant to be internal only */
+    pub(crate) function: F,
+}
+
+impl<T, E, F> BlockingRetryer<'_, T, E, F>
+where
+    F: FnMut() -> RetryResult<T, E>,
+{
+    pub fn run(mut self) -> Result<T, E> {
+        let mut attempts = AttemptLoop::new(self.policy.as_ref());
+        let result = loop {
+            attempts.acquire_blocking();
+            let result = (self.function)();
+            if let Some(result) = attempts.next_blocking(result) {
+                break result;
+            }
+        };
+        self.count = attempts.count();
+        result
+    }
+
     pub fn set_policy(&mut self, policy: RetryPolicy) {
         self.policy = util::OwnedOrRef::Owned(policy);
     }
     pub fn count(&self) -> u64 {
         self.count
     }
-}
\ No newline at end of file
+}
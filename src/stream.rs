@@ -0,0 +1,93 @@
+use crate::policy::RetryPolicy;
+use crate::retry_result::RetryResult;
+use crate::retryer::AttemptLoop;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::pin::Pin;
+
+/// Consumes a stream that can fail part of the way through, recreating it after errors that should be retried,
+/// so the consumer sees one continuous stream.
+///
+/// factory creates the stream, and is given the last item that was yielded (None the first time, or if nothing was yielded yet)
+/// so it can resume after it, from a page token or a change feed's cursor for example. The policy's delay is waited before
+/// each new stream is created. Errors that match retry_if are retried, all others are yielded and end the stream.
+///
+/// If the policy has a rate limiter, a permit is acquired before each stream is created.
+/// The policy's limit and deadline apply to consecutive failures: each item yielded resets the count. Once the policy stops
+/// retrying, the last error is yielded and the stream ends
+///
+/// # Example
+///
+/// ```rust, ignore
+/// let changes = resume_stream(
+///     &policy,
+///     |last: Option<&Change>| feed.subscribe(last.map(|change| change.sequence)),
+///     |e| e.is_transient(),
+/// );
+/// ```
+pub fn resume_stream<'a, T, E, S, F, P>(
+    policy: &'a RetryPolicy,
+    factory: F,
+    retry_if: P,
+) -> impl Stream<Item = Result<T, E>> + 'a
+where
+    T: Clone + 'a,
+    E: 'a,
+    S: Stream<Item = Result<T, E>> + 'a,
+    F: FnMut(Option<&T>) -> S + 'a,
+    P: FnMut(&E) -> bool + 'a,
+{
+    let state = Resume {
+        stream: None,
+        factory,
+        retry_if,
+        last: None,
+        failures: None,
+        done: false,
+    };
+    stream::unfold(state, move |mut state| async move {
+        if state.done {
+            return None;
+        }
+        loop {
+            let stream = match &mut state.stream {
+                Some(stream) => stream,
+                None => {
+                    policy.acquire_permit().await;
+                    state.stream.insert(Box::pin((state.factory)(state.last.as_ref())))
+                }
+            };
+            match stream.next().await {
+                Some(Ok(item)) => {
+                    state.failures = None;
+                    state.last = Some(item.clone());
+                    return Some((Ok(item), state));
+                }
+                Some(Err(e)) if (state.retry_if)(&e) => {
+                    let failures = state.failures.get_or_insert_with(|| AttemptLoop::new(policy));
+                    if let Some(Err(e)) = failures.next(RetryResult::<(), E>::Retry(e)).await {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                    failures.acquire().await;
+                    state.stream = Some(Box::pin((state.factory)(state.last.as_ref())));
+                }
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+                None => return None,
+            }
+        }
+    })
+}
+
+struct Resume<'a, T, S, F, P> {
+    /// None until the first poll
+    stream: Option<Pin<Box<S>>>,
+    factory: F,
+    retry_if: P,
+    last: Option<T>,
+    /// The retries of the current run of consecutive failures, None while the stream is yielding items
+    failures: Option<AttemptLoop<'a>>,
+    done: bool,
+}
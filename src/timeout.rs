@@ -0,0 +1,58 @@
+use crate::retry_result::RetryResult;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::time::Duration;
+
+/// Error produced when a single attempt takes longer than its timeout.
+///
+/// Retryable functions that use a timeout must have an error type that implements ```From<TimeoutError>```
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError {
+    pub timeout: Duration,
+}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "attempt timed out after {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Converts the TimeoutError of an attempt that timed out into the executor's error type
+pub type OnTimeout<E> = fn(TimeoutError) -> E;
+
+/// Runs a single attempt with a timeout. An attempt that times out is converted to a Retry
+/// holding the TimeoutError, so it counts towards the policy's limit like any other transient failure
+pub async fn with_timeout<T, E, F>(timeout: Duration, attempt: F) -> RetryResult<T, E>
+where
+    F: Future<Output = RetryResult<T, E>>,
+    E: From<TimeoutError>,
+{
+    match tokio::time::timeout(timeout, attempt).await {
+        Ok(result) => result,
+        Err(_) => RetryResult::Retry(TimeoutError { timeout }.into()),
+    }
+}
+
+/// Sets the timeout of each attempt on the builders #[retry_prepare] generates for async functions.
+///
+/// timeout() is a provided method so that it exists exactly when the error type converts from TimeoutError.
+/// The generated builder can't state that bound itself: a where clause on a concrete error type that doesn't
+/// implement From<TimeoutError> would stop the whole expansion from compiling
+pub trait TimeoutBuilder: Sized {
+    /// The error type of the prepared function
+    type Error;
+
+    /// Sets a timeout for each attempt, with on_timeout converting the TimeoutError into the error type
+    fn timeout_with(self, timeout: Duration, on_timeout: OnTimeout<Self::Error>) -> Self;
+
+    /// Sets a timeout for each attempt. See eztry::timeout::with_timeout
+    fn timeout(self, timeout: Duration) -> Self
+    where
+        Self::Error: From<TimeoutError>,
+    {
+        self.timeout_with(timeout, Self::Error::from)
+    }
+}
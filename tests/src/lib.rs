@@ -6,6 +6,8 @@ mod tests {
     use rand::Rng;
     use eztry::global;
     use eztry::prelude::*;
+    use std::time::Duration;
+    use std::sync::Arc;
 
     type DemoStructWithAsync = MutableAgent;
     /*the function here should always pass, it's to make sure that what I am passing can be passed to async functions*/
@@ -491,8 +493,13 @@ mod tests {
 
         let p = RetryPolicy {
             limit: RetryLimit::Limited(10),
-            base_delay: 500,
+            base_delay: Duration::from_millis(500),
             delay_time: constant_backoff,
+            backoff: Some(BackoffKind::Constant),
+            max_delay: None,
+            deadline: None,
+            jitter: Jitter::None,
+            rate_limiter: None,
         };
 
         ex.set_policy(p);
@@ -515,4 +522,1722 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn policies_config_applies_named_policies() {
+        use eztry::config::PoliciesConfig;
+
+        let config: PoliciesConfig = serde_json::from_str(
+            r#"{ "named": { "config_test": { "limit": { "Limited": 3 }, "base_delay": 1, "backoff": "linear" } } }"#,
+        )
+        .unwrap();
+        config.apply().unwrap();
+
+        let policy = global::get_named_policy("config_test").unwrap();
+        assert_eq!(policy.limit, RetryLimit::Limited(3));
+        assert_eq!(policy.base_delay, Duration::from_millis(1));
+
+        /* invalid policies are rejected as a whole, and nothing is applied */
+        let config: PoliciesConfig = serde_json::from_str(
+            r#"{ "named": {
+                "config_test": { "limit": { "Limited": 10 }, "base_delay": 1, "backoff": "linear" },
+                "config_test_invalid": { "limit": { "Limited": 0 }, "base_delay": 1, "backoff": "linear" }
+            } }"#,
+        )
+        .unwrap();
+        let err = config.apply().unwrap_err();
+        assert_eq!(err.policy.as_deref(), Some("config_test_invalid"));
+        assert_eq!(global::get_named_policy("config_test").unwrap().limit, RetryLimit::Limited(3));
+        assert!(global::get_named_policy("config_test_invalid").is_none());
+
+        assert!(global::remove_named_policy("config_test"));
+        assert!(global::get_named_policy("config_test").is_none());
+    }
+
+    fn watched_policy_file(name: &str) -> (std::path::PathBuf, impl Fn(&str)) {
+        let path = std::env::temp_dir().join(format!("eztry_reload_{name}_{}.json", std::process::id()));
+        let file = path.clone();
+        let write = move |named: &str| std::fs::write(&file, format!(r#"{{ "named": {{ {named} }} }}"#)).unwrap();
+        (path, write)
+    }
+
+    fn watched_policy(name: &str, limit: u64) -> String {
+        format!(r#""{name}": {{ "limit": {{ "Limited": {limit} }}, "base_delay": 1, "backoff": "constant" }}"#)
+    }
+
+    #[tokio::test]
+    async fn policy_watcher_reloads_changed_file() {
+        use eztry::reload::PolicyWatcher;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let (path, write) = watched_policy_file("changed");
+        let errors = Arc::new(AtomicUsize::new(0));
+        let error_count = errors.clone();
+        let mut watcher = PolicyWatcher::new(&path).on_error(move |_| {
+            error_count.fetch_add(1, Ordering::SeqCst);
+        });
+
+        write(&watched_policy("watched", 2));
+        watcher.poll().await;
+        assert_eq!(global::get_named_policy("watched").unwrap().limit, RetryLimit::Limited(2));
+
+        /* rewritten within the same second: detected by contents, not modification time */
+        write(&watched_policy("watched", 0));
+        watcher.poll().await;
+        assert_eq!(global::get_named_policy("watched").unwrap().limit, RetryLimit::Limited(2));
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+
+        /* the same error is only reported once */
+        watcher.poll().await;
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+
+        write(r#""watched": { "limit": "#);
+        watcher.poll().await;
+        assert_eq!(global::get_named_policy("watched").unwrap().limit, RetryLimit::Limited(2));
+        assert_eq!(errors.load(Ordering::SeqCst), 2);
+
+        write(&watched_policy("watched", 7));
+        watcher.poll().await;
+        assert_eq!(global::get_named_policy("watched").unwrap().limit, RetryLimit::Limited(7));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn policy_watcher_removes_deleted_policies_and_reports_missing_file_once() {
+        use eztry::reload::PolicyWatcher;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let (path, write) = watched_policy_file("removed");
+        let errors = Arc::new(AtomicUsize::new(0));
+        let error_count = errors.clone();
+        let mut watcher = PolicyWatcher::new(&path).on_error(move |_| {
+            error_count.fetch_add(1, Ordering::SeqCst);
+        });
+
+        watcher.poll().await;
+        watcher.poll().await;
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+
+        write(&format!("{}, {}", watched_policy("watched_kept", 2), watched_policy("watched_removed", 3)));
+        watcher.poll().await;
+        assert!(global::get_named_policy("watched_removed").is_some());
+        let kept = global::get_named_policy("watched_kept").unwrap();
+
+        write(&watched_policy("watched_kept", 2));
+        watcher.poll().await;
+        assert!(global::get_named_policy("watched_removed").is_none());
+        /* unchanged policies are not re-installed */
+        assert!(std::ptr::eq(kept, global::get_named_policy("watched_kept").unwrap()));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawned_policy_watcher_polls_on_interval() {
+        use eztry::reload::PolicyWatcher;
+
+        let (path, write) = watched_policy_file("spawned");
+        write(&watched_policy("watched_spawned", 2));
+        let handle = PolicyWatcher::new(&path).interval(Duration::from_secs(5)).spawn();
+
+        while global::get_named_policy("watched_spawned").is_none() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        write(&watched_policy("watched_spawned", 4));
+        while global::get_named_policy("watched_spawned").unwrap().limit != RetryLimit::Limited(4) {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        handle.abort();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn scoped_policy_overrides_default_policy() {
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Limited(3))
+            .backoff_policy(constant_backoff)
+            .base_delay(1)
+            .build();
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let res = eztry::with_policy(policy.clone(), default_executor(agent.clone())).await;
+        assert!(res.is_err());
+        assert_eq!(agent.count().await, 3);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let res = eztry::with_policy(policy, async {
+            /* nested scopes use the innermost policy */
+            let inner = RetryPolicy::builder()
+                .limit(RetryLimit::Limited(2))
+                .backoff_policy(constant_backoff)
+                .base_delay(1)
+                .build();
+            eztry::with_policy(inner, async {
+                (|| async {
+                    match agent.execute().await {
+                        Ok(_v) => Success(()),
+                        Err(_e) => Retry(()),
+                    }
+                })
+                .retry_with_default_policy()
+                .await
+            })
+            .await
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(agent.count().await, 2);
+    }
+
+    #[test]
+    fn try_build_reports_missing_fields() {
+        let err = RetryPolicyBuilder::new().base_delay(1).try_build().unwrap_err();
+        assert!(err.missing_limit());
+        assert!(err.missing_backoff_policy());
+        assert!(!err.missing_base_delay());
+    }
+
+    #[test]
+    fn try_build_rejects_invalid_policies() {
+        let err = RetryPolicyBuilder::new_with_defaults()
+            .base_delay(0)
+            .try_build()
+            .unwrap_err();
+        assert_eq!(err.violations(), &[PolicyViolation::HotLoop]);
+
+        let err = RetryPolicyBuilder::new_with_defaults()
+            .limit(RetryLimit::Limited(100))
+            .backoff_policy(exponential_backoff)
+            .try_build()
+            .unwrap_err();
+        assert!(matches!(err.violations(), [PolicyViolation::DelayOverflow { .. }]));
+
+        /* capping the delay prevents the overflow */
+        assert!(RetryPolicyBuilder::new_with_defaults()
+            .limit(RetryLimit::Limited(100))
+            .backoff_policy(exponential_backoff)
+            .max_delay(60_000)
+            .try_build()
+            .is_ok());
+
+        let err = RetryPolicyBuilder::new_with_defaults()
+            .base_delay(100)
+            .max_delay(50)
+            .deadline(10)
+            .try_build()
+            .unwrap_err();
+        assert_eq!(
+            err.violations(),
+            &[
+                PolicyViolation::MaxDelayBelowBaseDelay {
+                    max_delay: Duration::from_millis(50),
+                    base_delay: Duration::from_millis(100),
+                },
+                PolicyViolation::DeadlineBeforeFirstRetry {
+                    deadline: Duration::from_millis(10),
+                    first_delay: Duration::from_millis(50),
+                },
+            ]
+        );
+        assert!(err.to_string().contains("max_delay of 50ms is less than base_delay of 100ms"));
+
+        let err = RetryPolicyBuilder::new_with_defaults()
+            .limit(RetryLimit::Limited(0))
+            .try_build()
+            .unwrap_err();
+        assert_eq!(err.violations(), &[PolicyViolation::ZeroLimit]);
+    }
+
+    #[tokio::test]
+    async fn deadline_stops_retries() {
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Unlimited)
+            .backoff_policy(constant_backoff)
+            .base_delay(20)
+            .deadline(110)
+            .build();
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let res = (|| async {
+            match agent.execute().await {
+                Ok(_v) => Success(()),
+                Err(_e) => Retry(()),
+            }
+        })
+        .retry(&policy)
+        .await;
+
+        assert!(res.is_err());
+        let count = agent.count().await;
+        assert!((2..=6).contains(&count), "unexpected attempt count {count}");
+    }
+
+    #[test]
+    fn duration_based_delays() {
+        fn half_backoff(policy: &RetryPolicy, attempt: u64) -> Duration {
+            policy.base_delay * attempt as u32 / 2
+        }
+
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Limited(10))
+            .backoff_policy(linear_backoff)
+            .base_delay_duration(Duration::from_micros(500))
+            .max_delay_duration(Duration::from_micros(1800))
+            .build();
+        assert_eq!(policy.delay(1), Duration::from_micros(500));
+        assert_eq!(policy.delay(3), Duration::from_micros(1500));
+        assert_eq!(policy.delay(4), Duration::from_micros(1800));
+
+        /* the millisecond shorthand is equivalent */
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Limited(10))
+            .backoff_policy(half_backoff)
+            .base_delay(10)
+            .build();
+        assert_eq!(policy.base_delay, Duration::from_millis(10));
+        assert_eq!(policy.delay(3), Duration::from_millis(15));
+    }
+
+    #[test]
+    fn policy_schedule_respects_limit_cap_and_deadline() {
+        let ms = Duration::from_millis;
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Limited(6))
+            .backoff_policy(exponential_backoff)
+            .base_delay(100)
+            .max_delay(500)
+            .build();
+
+        let delays: Vec<_> = policy.schedule().map(|s| s.delay).collect();
+        assert_eq!(delays, vec![ms(100), ms(200), ms(400), ms(500), ms(500)]);
+        let last = policy.schedule().last().unwrap();
+        assert_eq!(last.attempt, 5);
+        assert_eq!(last.elapsed, ms(1700));
+
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Unlimited)
+            .backoff_policy(linear_backoff)
+            .base_delay(100)
+            .deadline(650)
+            .build();
+        let elapsed: Vec<_> = policy.schedule().map(|s| s.elapsed).collect();
+        assert_eq!(elapsed, vec![ms(100), ms(300), ms(600)]);
+
+        let unlimited = RetryPolicyBuilder::new_with_defaults().build();
+        assert_eq!(unlimited.schedule().take(100).count(), 100);
+    }
+
+    #[test]
+    fn policy_describes_backoff_by_name() {
+        fn custom(policy: &RetryPolicy, _attempt: u64) -> Duration {
+            policy.base_delay
+        }
+
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Limited(5))
+            .backoff(BackoffKind::Exponential)
+            .base_delay(100)
+            .max_delay(5000)
+            .build();
+        assert_eq!(policy.backoff_name(), Some("exponential"));
+        assert_eq!(
+            policy.to_string(),
+            "up to 5 attempts, exponential backoff from 100ms, capped at 5s"
+        );
+        assert!(format!("{policy:?}").contains(r#"backoff: "exponential""#));
+
+        let policy = RetryPolicyBuilder::new_with_defaults()
+            .backoff_policy(custom)
+            .deadline(30_000)
+            .build();
+        assert_eq!(policy.backoff_name(), None);
+        assert_eq!(
+            policy.to_string(),
+            "unlimited attempts, custom backoff from 1s, within 30s"
+        );
+
+        /* backoff functions are not identified by address, even when they are the built-in one */
+        let policy = RetryPolicyBuilder::new_with_defaults()
+            .backoff_policy(constant_backoff)
+            .build();
+        assert_eq!(policy.backoff_name(), None);
+        assert_eq!(RetryPolicyBuilder::new_with_defaults().build().backoff_name(), Some("constant"));
+    }
+
+    struct Counter {
+        attempts: u64,
+        succeed_on: u64,
+    }
+
+    fn three_quick_attempts() -> RetryPolicy {
+        RetryPolicy::builder()
+            .limit(RetryLimit::Limited(3))
+            .backoff_policy(constant_backoff)
+            .base_delay(1)
+            .build()
+    }
+
+    impl Counter {
+        #[retry(three_quick_attempts)]
+        async fn increment(&mut self, step: u64) -> RetryResult<u64, u64> {
+            self.attempts += step;
+            if self.attempts >= self.succeed_on {
+                Success(self.attempts)
+            } else {
+                Retry(self.attempts)
+            }
+        }
+
+        #[retry("method_test")]
+        async fn check(&self, label: String, buf: &mut Vec<String>) -> RetryResult<(), u64> {
+            buf.push(label);
+            if buf.len() as u64 >= self.succeed_on {
+                Success(())
+            } else {
+                Retry(buf.len() as u64)
+            }
+        }
+    }
+
+    trait Increment {
+        async fn bump(&mut self) -> Result<u64, u64>;
+    }
+
+    impl Increment for Counter {
+        #[retry]
+        async fn bump(&mut self) -> RetryResult<u64, u64> {
+            self.attempts += 1;
+            if self.attempts >= self.succeed_on {
+                return Success(self.attempts);
+            }
+            Retry(self.attempts)
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_on_mut_self_method() {
+        let mut counter = Counter { attempts: 0, succeed_on: 4 };
+        assert_eq!(counter.increment(2).await, Ok(4));
+        assert_eq!(counter.attempts, 4);
+
+        let mut counter = Counter { attempts: 0, succeed_on: 100 };
+        assert_eq!(counter.increment(1).await, Err(3));
+    }
+
+    #[tokio::test]
+    async fn retry_on_ref_self_method_uses_named_policy() {
+        global::set_named_policy(
+            "method_test",
+            RetryPolicy::builder()
+                .limit(RetryLimit::Limited(2))
+                .backoff_policy(constant_backoff)
+                .base_delay(1)
+                .build(),
+        );
+
+        let counter = Counter { attempts: 0, succeed_on: 5 };
+        let mut buf = Vec::new();
+        let res = counter.check("attempt".to_string(), &mut buf).await;
+        assert_eq!(res, Err(2));
+        assert_eq!(buf.len(), 2);
+    }
+
+    #[retry("unregistered_policy")]
+    async fn unregistered_named_policy() -> RetryResult<(), ()> {
+        Retry(())
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "no retry policy is registered under the name 'unregistered_policy'")]
+    async fn unregistered_named_policy_panics() {
+        let _ = unregistered_named_policy().await;
+    }
+
+    #[retry(limit = 100, backoff = exponential, base_delay = 1)]
+    async fn overflowing_inline_policy() -> RetryResult<(), ()> {
+        Success(())
+    }
+
+    #[retry(limit = 3, base_delay = 1000, deadline = 10)]
+    async fn deadline_before_first_retry() -> RetryResult<(), ()> {
+        Success(())
+    }
+
+    /* these are only found by RetryPolicy::validate, which computes the backoff */
+    #[tokio::test]
+    #[should_panic(expected = "invalid #[retry] arguments: invalid retry policy")]
+    async fn overflowing_inline_policy_panics() {
+        let _ = overflowing_inline_policy().await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "invalid #[retry] arguments: invalid retry policy")]
+    async fn inline_deadline_before_first_retry_panics() {
+        let _ = deadline_before_first_retry().await;
+    }
+
+    #[tokio::test]
+    async fn retry_on_trait_impl_method_uses_scoped_policy() {
+        let mut counter = Counter { attempts: 0, succeed_on: 100 };
+        let res = eztry::with_policy(three_quick_attempts(), counter.bump()).await;
+        assert_eq!(res, Err(3));
+
+        let mut counter = Counter { attempts: 0, succeed_on: 2 };
+        let res = eztry::with_policy(three_quick_attempts(), counter.bump()).await;
+        assert_eq!(res, Ok(2));
+    }
+
+    #[retry(limit = 4, backoff = exponential, base_delay = "1ms", max_delay = "3ms", jitter)]
+    async fn inline_policy_executor(agent: MutableAgent) -> RetryResult<u32, u32> {
+        match agent.execute().await {
+            Ok(val) => Success(val.get().unwrap() as u32),
+            Err(val) => Retry(val.get().unwrap() as u32),
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum SlowError {
+        TimedOut,
+    }
+
+    impl From<eztry::timeout::TimeoutError> for SlowError {
+        fn from(_: eztry::timeout::TimeoutError) -> Self {
+            SlowError::TimedOut
+        }
+    }
+
+    #[retry(limit = 3, base_delay = 1, timeout = "10ms")]
+    async fn slow_executor(agent: MutableAgent) -> RetryResult<(), SlowError> {
+        let _ = agent.execute().await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        Success(())
+    }
+
+    #[tokio::test]
+    async fn retry_with_inline_policy_arguments() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let res = inline_policy_executor(agent.clone()).await;
+        assert_eq!(res, Err(4));
+        assert_eq!(agent.count().await, 4);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(3));
+        let res = inline_policy_executor(agent.clone()).await;
+        assert_eq!(res, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn retry_attempts_time_out() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysSucceed);
+        let res = slow_executor(agent.clone()).await;
+        assert_eq!(res, Err(SlowError::TimedOut));
+        assert_eq!(agent.count().await, 3);
+    }
+
+    #[test]
+    fn jitter_stays_within_delay() {
+        let delay = Duration::from_millis(100);
+        for _ in 0..100 {
+            assert!(Jitter::Full.apply(delay) <= delay);
+            let equal = Jitter::Equal.apply(delay);
+            assert!(equal >= delay / 2 && equal <= delay);
+        }
+        assert_eq!(Jitter::None.apply(delay), delay);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum AppError {
+        Transient(u64),
+        Fatal,
+    }
+
+    type AppResult<T> = Result<T, AppError>;
+
+    fn is_transient(e: &AppError) -> bool {
+        matches!(e, AppError::Transient(_))
+    }
+
+    async fn fallible_call(agent: &MutableAgent) -> AppResult<u64> {
+        match agent.execute().await {
+            Ok(v) => Ok(v.get().unwrap() as u64),
+            Err(v) => Err(AppError::Transient(v.get().unwrap() as u64)),
+        }
+    }
+
+    #[retry(retry_if = is_transient, limit = 5, base_delay = 1)]
+    async fn classified_executor(agent: MutableAgent, fatal_after: u64) -> AppResult<u64> {
+        if agent.count().await >= fatal_after {
+            return Err(AppError::Fatal);
+        }
+        let value = fallible_call(&agent).await?;
+        Ok(value)
+    }
+
+    #[retry(abort_if = |e: &AppError| *e == AppError::Fatal, limit = 3, base_delay = 1)]
+    async fn abort_classified_executor(agent: &MutableAgent) -> Result<u64, AppError> {
+        fallible_call(agent).await
+    }
+
+    #[tokio::test]
+    async fn retry_if_classifies_plain_results() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(3));
+        assert_eq!(classified_executor(agent.clone(), 100).await, Ok(3));
+        assert_eq!(agent.count().await, 3);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        assert_eq!(
+            classified_executor(agent.clone(), 100).await,
+            Err(AppError::Transient(5))
+        );
+
+        /* non-transient errors abort immediately */
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        assert_eq!(classified_executor(agent.clone(), 2).await, Err(AppError::Fatal));
+        assert_eq!(agent.count().await, 2);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        assert_eq!(
+            abort_classified_executor(&agent).await,
+            Err(AppError::Transient(3))
+        );
+    }
+
+    trait Source {
+        fn value(&self) -> u64;
+    }
+
+    #[derive(Clone)]
+    struct Fixed(u64);
+
+    impl Source for Fixed {
+        fn value(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[retry(three_quick_attempts)]
+    async fn generic_executor<S: Source + Clone>(
+        source: S,
+        agent: MutableAgent,
+    ) -> RetryResult<u64, u64> {
+        match agent.execute().await {
+            Ok(_) => Success(source.value()),
+            Err(v) => Retry(v.get().unwrap() as u64),
+        }
+    }
+
+    #[retry_prepare]
+    async fn generic_prepared<S>(source: S, agent: MutableAgent) -> RetryResult<u64, u64>
+    where
+        S: Source + Clone + 'static,
+    {
+        match agent.execute().await {
+            Ok(_) => Success(source.value()),
+            Err(v) => Retry(v.get().unwrap() as u64),
+        }
+    }
+
+    #[retry_prepare]
+    async fn const_generic_prepared<'a, const N: usize>(
+        bytes: [u8; N],
+        label: &'a str,
+    ) -> RetryResult<usize, ()> {
+        Success(bytes.len() + label.len())
+    }
+
+    #[retry(three_quick_attempts)]
+    async fn impl_trait_executor(
+        source: impl Source + Clone,
+        labels: &[impl AsRef<str>],
+        agent: MutableAgent,
+    ) -> RetryResult<u64, u64> {
+        match agent.execute().await {
+            Ok(_) => Success(source.value() + labels.len() as u64),
+            Err(v) => Retry(v.get().unwrap() as u64),
+        }
+    }
+
+    #[retry_prepare]
+    async fn impl_trait_prepared(
+        source: impl Source + Clone + 'static,
+        agent: MutableAgent,
+    ) -> RetryResult<u64, u64> {
+        match agent.execute().await {
+            Ok(_) => Success(source.value()),
+            Err(v) => Retry(v.get().unwrap() as u64),
+        }
+    }
+
+    #[tokio::test]
+    async fn generic_functions_are_retried() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        assert_eq!(generic_executor(Fixed(7), agent.clone()).await, Ok(7));
+        assert_eq!(agent.count().await, 2);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        let res = three_quick_attempts()
+            .call(generic_prepared(Fixed(9), agent.clone()))
+            .await;
+        assert_eq!(res, Ok(9));
+        assert_eq!(agent.count().await, 2);
+
+        let res = three_quick_attempts()
+            .call(const_generic_prepared([1, 2, 3], "ab"))
+            .await;
+        assert_eq!(res, Ok(5));
+    }
+
+    #[tokio::test]
+    async fn impl_trait_arguments_are_retried() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        assert_eq!(impl_trait_executor(Fixed(7), &["a", "b"], agent.clone()).await, Ok(9));
+        assert_eq!(agent.count().await, 2);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        let res = three_quick_attempts()
+            .call(impl_trait_prepared(Fixed(9), agent.clone()))
+            .await;
+        assert_eq!(res, Ok(9));
+        assert_eq!(agent.count().await, 2);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(1));
+        let res = impl_trait_prepared::builder()
+            .source(Fixed(3))
+            .agent(agent)
+            .policy(three_quick_attempts())
+            .await;
+        assert_eq!(res, Ok(3));
+    }
+
+    mod retried_items {
+        use super::three_quick_attempts;
+        use eztry::prelude::*;
+
+        /// Adds up a pair, retrying until the running total reaches the target
+        #[retry(three_quick_attempts)]
+        pub(crate) async fn destructured(
+            (a, b): (u64, u64),
+            _: String,
+            mut target: u64,
+            offset: u64,
+        ) -> RetryResult<u64, u64> {
+            target -= offset;
+            if a + b >= target {
+                Success(a + b)
+            } else {
+                Retry(a + b)
+            }
+        }
+
+        #[retry(retry_if = |_: &u64| true, limit = 2, base_delay = 1)]
+        pub async fn destructured_classified(
+            (a, b): (u64, u64),
+            &scale: &u64,
+        ) -> Result<u64, u64> {
+            Ok(a * b * scale)
+        }
+
+        #[cfg(test)]
+        #[retry_prepare]
+        pub async fn cfg_selected(value: u64) -> RetryResult<u64, ()> {
+            Success(value)
+        }
+
+        /* removed by cfg, otherwise it would conflict with the function above */
+        #[cfg(not(test))]
+        #[retry_prepare]
+        pub async fn cfg_selected(value: u64) -> RetryResult<u64, ()> {
+            Abort(())
+        }
+
+        #[cfg(not(test))]
+        #[retry]
+        pub async fn destructured_classified() -> RetryResult<u64, ()> {
+            Abort(())
+        }
+    }
+
+    #[tokio::test]
+    async fn macros_keep_visibility_attributes_and_patterns() {
+        assert_eq!(
+            retried_items::destructured((1, 2), "unused".into(), 4, 1).await,
+            Ok(3)
+        );
+        assert_eq!(
+            retried_items::destructured((1, 1), "unused".into(), 10, 0).await,
+            Err(2)
+        );
+        assert_eq!(retried_items::destructured_classified((3, 4), &2).await, Ok(24));
+        let res = three_quick_attempts()
+            .call(retried_items::cfg_selected(7))
+            .await;
+        assert_eq!(res, Ok(7));
+    }
+
+    /* deliberately not Clone */
+    struct Payload {
+        bytes: Vec<u8>,
+    }
+
+    #[retry(three_quick_attempts)]
+    async fn lent_payload(#[by_ref] payload: Payload, agent: MutableAgent) -> RetryResult<usize, u64> {
+        match agent.execute().await {
+            Ok(_) => Success(payload.bytes.len()),
+            Err(v) => Retry(v.get().unwrap() as u64),
+        }
+    }
+
+    #[retry(by_ref, limit = 3, base_delay = 1)]
+    async fn all_lent(payload: Payload, agent: MutableAgent) -> RetryResult<usize, u64> {
+        match agent.execute().await {
+            Ok(_) => Success(payload.bytes.len()),
+            Err(v) => Retry(v.get().unwrap() as u64),
+        }
+    }
+
+    #[retry_prepare]
+    async fn prepared_lent(#[by_ref] payload: Payload, label: &str) -> RetryResult<usize, ()> {
+        Success(payload.bytes.len() + label.len())
+    }
+
+    #[retry(three_quick_attempts)]
+    async fn borrowed_arguments(label: &str, seen: &mut Vec<String>) -> RetryResult<usize, usize> {
+        seen.push(label.to_string());
+        if seen.len() >= 2 {
+            Success(seen.len())
+        } else {
+            Retry(seen.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn arguments_are_lent_instead_of_cloned() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        let payload = Payload { bytes: vec![0; 16] };
+        assert_eq!(lent_payload(payload, agent.clone()).await, Ok(16));
+        assert_eq!(agent.count().await, 2);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(3));
+        let payload = Payload { bytes: vec![0; 4] };
+        assert_eq!(all_lent(payload, agent.clone()).await, Ok(4));
+        assert_eq!(agent.count().await, 3);
+
+        let payload = Payload { bytes: vec![0; 2] };
+        let res = three_quick_attempts()
+            .call(prepared_lent(payload, "abc"))
+            .await;
+        assert_eq!(res, Ok(5));
+
+        let mut seen = Vec::new();
+        assert_eq!(borrowed_arguments("label", &mut seen).await, Ok(2));
+        assert_eq!(seen, vec!["label", "label"]);
+    }
+
+    #[retry(limit = 3, base_delay = 1)]
+    fn blocking_executor(attempts: &mut u64, succeed_on: u64) -> RetryResult<u64, u64> {
+        *attempts += 1;
+        if *attempts >= succeed_on {
+            Success(*attempts)
+        } else {
+            Retry(*attempts)
+        }
+    }
+
+    #[retry(retry_if = is_transient, limit = 3, base_delay = 1)]
+    fn blocking_classified(attempts: &mut u64, fatal_after: u64) -> AppResult<u64> {
+        *attempts += 1;
+        if *attempts >= fatal_after {
+            return Err(AppError::Fatal);
+        }
+        Err(AppError::Transient(*attempts))
+    }
+
+    #[retry_prepare]
+    fn blocking_prepared(value: u64) -> RetryResult<u64, ()> {
+        Success(value * 2)
+    }
+
+    impl Counter {
+        #[retry(three_quick_attempts)]
+        fn increment_blocking(&mut self) -> RetryResult<u64, u64> {
+            self.attempts += 1;
+            if self.attempts >= self.succeed_on {
+                Success(self.attempts)
+            } else {
+                Retry(self.attempts)
+            }
+        }
+    }
+
+    #[test]
+    fn blocking_functions_are_retried() {
+        let mut attempts = 0;
+        assert_eq!(blocking_executor(&mut attempts, 2), Ok(2));
+        let mut attempts = 0;
+        assert_eq!(blocking_executor(&mut attempts, 10), Err(3));
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        assert_eq!(
+            blocking_classified(&mut attempts, 10),
+            Err(AppError::Transient(3))
+        );
+        let mut attempts = 0;
+        assert_eq!(blocking_classified(&mut attempts, 2), Err(AppError::Fatal));
+        assert_eq!(attempts, 2);
+
+        assert_eq!(three_quick_attempts().call_blocking(blocking_prepared(4)), Ok(8));
+        assert_eq!(blocking_prepared(5).retry_with_policy(three_quick_attempts()), Ok(10));
+
+        let mut counter = Counter {
+            attempts: 0,
+            succeed_on: 3,
+        };
+        assert_eq!(counter.increment_blocking(), Ok(3));
+
+        let mut attempts = 0;
+        let res = three_quick_attempts().call_closure_blocking(|| {
+            attempts += 1;
+            if attempts == 2 { Success(attempts) } else { Retry(attempts) }
+        });
+        assert_eq!(res, Ok(2));
+    }
+
+    #[async_trait]
+    trait Service {
+        async fn call(&self, request: String) -> Result<u64, u64>;
+
+        async fn sum(&mut self, pair: (u64, u64)) -> Result<u64, u64>;
+
+        #[retry(three_quick_attempts)]
+        async fn call_twice(&self, agent: MutableAgent) -> RetryResult<u64, u64> {
+            match agent.execute().await {
+                Ok(v) => Success(v.get().unwrap() as u64),
+                Err(v) => Retry(v.get().unwrap() as u64),
+            }
+        }
+    }
+
+    struct RemoteService {
+        agent: MutableAgent,
+        sums: u64,
+    }
+
+    #[async_trait]
+    impl Service for RemoteService {
+        #[retry(three_quick_attempts)]
+        async fn call(&self, request: String) -> RetryResult<u64, u64> {
+            match self.agent.execute().await {
+                Ok(_) => Success(request.len() as u64),
+                Err(v) => Retry(v.get().unwrap() as u64),
+            }
+        }
+
+        #[retry(limit = 3, base_delay = 1)]
+        async fn sum(&mut self, (a, b): (u64, u64)) -> RetryResult<u64, u64> {
+            self.sums += 1;
+            if self.sums >= 2 {
+                Success(a + b)
+            } else {
+                Retry(self.sums)
+            }
+        }
+    }
+
+    trait Ping {
+        #[retry(three_quick_attempts)]
+        async fn ping(&self, agent: &MutableAgent) -> RetryResult<(), u64> {
+            match agent.execute().await {
+                Ok(_) => Success(()),
+                Err(v) => Retry(v.get().unwrap() as u64),
+            }
+        }
+    }
+
+    impl Ping for Counter {}
+
+    /* #[async_trait] here is eztry::async_trait from the prelude, which expands #[retry] before async-trait boxes the methods */
+    #[tokio::test]
+    async fn retry_on_async_trait_methods() {
+        let mut service = RemoteService {
+            agent: FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2)),
+            sums: 0,
+        };
+        assert_eq!(service.call("hello".to_string()).await, Ok(5));
+        assert_eq!(service.agent.count().await, 2);
+        assert_eq!(service.sum((2, 3)).await, Ok(5));
+        assert_eq!(service.sums, 2);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(3));
+        assert_eq!(service.call_twice(agent.clone()).await, Ok(3));
+
+        /* async_trait methods keep their Send futures */
+        let handle = tokio::spawn(async move {
+            let service: Box<dyn Service + Send + Sync> = Box::new(service);
+            service.call("spawned".to_string()).await
+        });
+        assert_eq!(handle.await.unwrap(), Ok(7));
+
+        let counter = Counter { attempts: 0, succeed_on: 1 };
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        assert_eq!(counter.ping(&agent).await, Ok(()));
+        assert_eq!(agent.count().await, 2);
+    }
+
+    struct Fetch {
+        agent: MutableAgent,
+        url: String,
+    }
+
+    #[executor]
+    impl Fetch {
+        fn new(agent: MutableAgent, url: &str) -> Self {
+            Self {
+                agent,
+                url: url.to_string(),
+            }
+        }
+
+        #[attempt]
+        async fn attempt(&self) -> RetryResult<usize, u64> {
+            match self.agent.execute().await {
+                Ok(_) => Success(self.url.len()),
+                Err(v) => Retry(v.get().unwrap() as u64),
+            }
+        }
+
+        async fn describe(&self) -> String {
+            format!("fetch {}", self.url)
+        }
+    }
+
+    #[tokio::test]
+    async fn executor_attribute_implements_executor() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        let fetch = Fetch::new(agent.clone(), "example");
+        assert_eq!(fetch.retry_with_policy(three_quick_attempts()).await, Ok(7));
+        assert_eq!(agent.count().await, 2);
+        assert_eq!(fetch.describe().await, "fetch example");
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let fetch = Fetch::new(agent.clone(), "example");
+        let mut retryer = fetch.prepare();
+        retryer.set_policy(three_quick_attempts());
+        assert_eq!(retryer.run().await, Err(3));
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(3));
+        let res = three_quick_attempts()
+            .call(Fetch::new(agent.clone(), "abc"))
+            .await;
+        assert_eq!(res, Ok(3));
+    }
+
+    #[retry_prepare]
+    async fn fetch_page(agent: MutableAgent, url: &str, delay: u64) -> RetryResult<usize, SlowError> {
+        let _ = agent.execute().await;
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        Success(url.len())
+    }
+
+    #[tokio::test]
+    async fn prepared_functions_have_builders() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysSucceed);
+        let res = fetch_page::builder()
+            .url("https://example.com")
+            .agent(agent.clone())
+            .delay(0)
+            .policy(three_quick_attempts())
+            .run()
+            .await;
+        assert_eq!(res, Ok(19));
+        assert_eq!(agent.count().await, 1);
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysSucceed);
+        let res = fetch_page::builder()
+            .agent(agent.clone())
+            .url("slow")
+            .delay(200)
+            .policy(three_quick_attempts())
+            .timeout(Duration::from_millis(10))
+            .run()
+            .await;
+        assert_eq!(res, Err(SlowError::TimedOut));
+        assert_eq!(agent.count().await, 3);
+
+        let res = three_quick_attempts().call_blocking(blocking_prepared::builder().value(3).build());
+        assert_eq!(res, Ok(6));
+        assert_eq!(blocking_prepared::builder().value(4).run(), Ok(8));
+    }
+
+    #[retry_prepare]
+    async fn reserved_argument_names(policy: u64, run: u64, label: &str) -> RetryResult<u64, ()> {
+        Success(policy + run + label.len() as u64)
+    }
+
+    /* a builder with a missing argument doesn't compile, so only complete builders can be tested here */
+    #[tokio::test]
+    async fn prepared_builder_renames_reserved_setters() {
+        let res = reserved_argument_names::builder()
+            .policy_arg(1)
+            .label("abc")
+            .run_arg(2)
+            .policy(three_quick_attempts())
+            .await;
+        assert_eq!(res, Ok(6));
+
+        /* setting an argument again replaces it */
+        let prepared = reserved_argument_names::builder()
+            .policy_arg(1)
+            .run_arg(2)
+            .label("abc")
+            .policy_arg(10)
+            .build();
+        assert_eq!(three_quick_attempts().call(prepared).await, Ok(15));
+
+        /* the error type doesn't convert from TimeoutError, so only timeout_with is available */
+        let res = reserved_argument_names::builder()
+            .policy_arg(1)
+            .run_arg(2)
+            .label("abc")
+            .timeout_with(Duration::from_secs(1), |_| ())
+            .await;
+        assert_eq!(res, Ok(6));
+    }
+
+    struct Background {
+        agent: MutableAgent,
+        retryers: Vec<OwnedRetryer<usize, u64>>,
+    }
+
+    #[tokio::test]
+    async fn owned_retryer_spawn() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        let mut retryer = Fetch::new(agent.clone(), "spawned").prepare_owned();
+        retryer.set_policy(three_quick_attempts());
+        let res = retryer.spawn().await.unwrap();
+        assert_eq!(res, Ok(7));
+        assert_eq!(agent.count().await, 2);
+
+        /* owned retryers can be stored, and shared executors retried without moving them */
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let shared: Arc<dyn Executor<usize, u64>> = Arc::new(Fetch::new(agent.clone(), "shared"));
+        let mut background = Background {
+            agent: agent.clone(),
+            retryers: Vec::new(),
+        };
+        for _ in 0..2 {
+            let mut retryer = OwnedRetryer::new(shared.clone());
+            retryer.set_policy(three_quick_attempts());
+            background.retryers.push(retryer);
+        }
+        let handles: Vec<_> = background.retryers.drain(..).map(OwnedRetryer::spawn).collect();
+        for handle in handles {
+            assert!(handle.await.unwrap().is_err());
+        }
+        assert_eq!(background.agent.count().await, 6);
+    }
+
+    #[tokio::test]
+    async fn prepared_retries_can_be_awaited() {
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(2));
+        let fetch = Fetch::new(agent.clone(), "awaited");
+        let mut retryer = fetch.prepare();
+        retryer.set_policy(three_quick_attempts());
+        assert_eq!(retryer.await, Ok(7));
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysSucceed);
+        let url = String::from("borrowed");
+        let page = fetch_page::builder()
+            .agent(agent.clone())
+            .url(&url)
+            .delay(0)
+            .policy(three_quick_attempts());
+        let generic = generic_prepared::builder()
+            .source(Fixed(4))
+            .agent(agent.clone())
+            .policy(three_quick_attempts());
+        let (page, generic) = tokio::join!(page, generic);
+        assert_eq!(page, Ok(8));
+        assert_eq!(generic, Ok(4));
+
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let mut slow = Fetch::new(agent.clone(), "slow").prepare_owned();
+        slow.set_policy(
+            RetryPolicy::builder()
+                .limit(RetryLimit::Unlimited)
+                .backoff_policy(constant_backoff)
+                .base_delay(1000)
+                .build(),
+        );
+        let mut attempts = 0;
+        let mut closure = ClosureRetryer::new(async || {
+            attempts += 1;
+            if attempts < 3 { Retry(0) } else { Success(attempts) }
+        });
+        closure.set_policy(three_quick_attempts());
+        let res = tokio::select! {
+            res = slow => res,
+            res = closure => Ok(res.unwrap() as usize),
+        };
+        assert_eq!(res, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn stateful_closures_are_retried() {
+        let endpoints = ["primary", "secondary", "fallback"];
+        let mut next = 0;
+        let mut tried = Vec::new();
+        let res = (async || {
+            let endpoint = endpoints[next % endpoints.len()];
+            next += 1;
+            tried.push(endpoint);
+            if endpoint == "fallback" { Success(endpoint) } else { Retry(endpoint) }
+        })
+        .retry(&three_quick_attempts())
+        .await;
+        assert_eq!(res, Ok("fallback"));
+        assert_eq!(tried, ["primary", "secondary", "fallback"]);
+
+        /* a plain closure returning a future, moved into a spawned task */
+        let agent = FallibleAgent::mutable(FallibleBehaviour::SucceedAfter(3));
+        let mut remaining = 5u64;
+        let task_agent = agent.clone();
+        let handle = tokio::spawn(async move {
+            three_quick_attempts()
+                .call_closure(move || {
+                    remaining -= 1;
+                    let agent = task_agent.clone();
+                    async move {
+                        match agent.execute().await {
+                            Ok(_) => Success(remaining),
+                            Err(_) => Retry(remaining),
+                        }
+                    }
+                })
+                .await
+        });
+        assert_eq!(handle.await.unwrap(), Ok(2));
+        assert_eq!(agent.count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn batch_retries_only_failed_items() {
+        /* item n succeeds on its nth submission, negative items are rejected */
+        let items = vec![1, 2, -1, 3, 5];
+        let mut submissions: Vec<Vec<i32>> = Vec::new();
+        let results = three_quick_attempts()
+            .call_batch(items, async |pending: &[i32]| {
+                submissions.push(pending.to_vec());
+                let round = submissions.len() as i32;
+                pending
+                    .iter()
+                    .map(|&item| match item {
+                        i if i < 0 => Abort(format!("rejected {i}")),
+                        i if i <= round => Success(i * 10),
+                        i => Retry(format!("throttled {i}")),
+                    })
+                    .collect()
+            })
+            .await;
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(10),
+                Ok(20),
+                Err("rejected -1".to_string()),
+                Ok(30),
+                Err("throttled 5".to_string()),
+            ]
+        );
+        assert_eq!(submissions, vec![vec![1, 2, -1, 3, 5], vec![2, 3, 5], vec![3, 5]]);
+
+        let results = retry_batch(&three_quick_attempts(), Vec::<u8>::new(), async |_: &[u8]| {
+            Vec::<RetryResult<(), ()>>::new()
+        })
+        .await;
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "batch submission returned 1 results for 2 items")]
+    async fn batch_requires_a_result_per_item() {
+        let _ = three_quick_attempts()
+            .call_batch(vec![1, 2], async |_: &[i32]| vec![Success::<(), ()>(())])
+            .await;
+    }
+
+    struct Tracked {
+        id: usize,
+        failures: std::sync::atomic::AtomicU64,
+        attempts: Arc<std::sync::atomic::AtomicU64>,
+        in_flight: Arc<std::sync::atomic::AtomicUsize>,
+        peak: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Executor<usize, usize> for Tracked {
+        async fn execute(&self) -> RetryResult<usize, usize> {
+            use std::sync::atomic::Ordering::SeqCst;
+            self.attempts.fetch_add(1, SeqCst);
+            let running = self.in_flight.fetch_add(1, SeqCst) + 1;
+            self.peak.fetch_max(running, SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.in_flight.fetch_sub(1, SeqCst);
+            match self.failures.fetch_update(SeqCst, SeqCst, |f| f.checked_sub(1)) {
+                Ok(_) => Retry(self.id),
+                Err(_) => Success(self.id),
+            }
+        }
+    }
+
+    fn tracked(count: usize, failures: u64) -> (Vec<Tracked>, Arc<std::sync::atomic::AtomicU64>, Arc<std::sync::atomic::AtomicUsize>) {
+        let attempts = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let executors = (0..count)
+            .map(|id| Tracked {
+                id,
+                failures: std::sync::atomic::AtomicU64::new(failures),
+                attempts: attempts.clone(),
+                in_flight: in_flight.clone(),
+                peak: peak.clone(),
+            })
+            .collect();
+        (executors, attempts, peak)
+    }
+
+    #[tokio::test]
+    async fn concurrent_retries_are_bounded() {
+        use futures_util::StreamExt;
+        use std::sync::atomic::Ordering::SeqCst;
+
+        let policy = three_quick_attempts();
+        let (executors, attempts, peak) = tracked(6, 1);
+        let results: Vec<_> = policy.concurrent(executors, 2).stream().collect().await;
+        assert_eq!(results.len(), 6);
+        assert!(results.iter().all(|(index, result)| result == &Ok(*index)));
+        assert_eq!(attempts.load(SeqCst), 12);
+        /* no more than two attempts were ever in flight at once, whatever order they finished in */
+        assert!(peak.load(SeqCst) <= 2);
+
+        /* once the shared budget is spent, failures are final */
+        let budget = RetryBudget::new(2);
+        let (executors, attempts, _) = tracked(4, u64::MAX);
+        let results: Vec<_> = policy
+            .concurrent_stream(futures_util::stream::iter(executors), 4)
+            .budget(budget.clone())
+            .stream()
+            .collect()
+            .await;
+        assert!(results.iter().all(|(index, result)| result == &Err(*index)));
+        assert_eq!(attempts.load(SeqCst), 6);
+        assert_eq!(budget.remaining(), 0);
+    }
+
+    /// A feed of 1..=6 that drops the connection after every two items
+    fn feed(after: Option<&u64>) -> impl futures_util::Stream<Item = Result<u64, String>> {
+        let start = after.map_or(1, |last| last + 1);
+        let items: Vec<Result<u64, String>> = (start..=6)
+            .take(2)
+            .map(Ok)
+            .chain((start + 2 <= 6).then(|| Err("connection reset".to_string())))
+            .collect();
+        futures_util::stream::iter(items)
+    }
+
+    #[tokio::test]
+    async fn streams_resume_after_transient_errors() {
+        use futures_util::StreamExt;
+
+        let policy = three_quick_attempts();
+        let mut subscriptions = Vec::new();
+        let items: Vec<_> = policy
+            .resume_stream(
+                |last: Option<&u64>| {
+                    subscriptions.push(last.copied());
+                    feed(last)
+                },
+                |e: &String| e == "connection reset",
+            )
+            .collect()
+            .await;
+        assert_eq!(items, (1..=6).map(Ok).collect::<Vec<_>>());
+        assert_eq!(subscriptions, vec![None, Some(2), Some(4)]);
+
+        /* errors that aren't retried end the stream */
+        let items: Vec<_> = policy
+            .resume_stream(feed, |e: &String| e == "timeout")
+            .collect()
+            .await;
+        assert_eq!(items, vec![Ok(1), Ok(2), Err("connection reset".to_string())]);
+
+        /* the policy limits consecutive failures */
+        let mut subscriptions = 0;
+        let items: Vec<Result<u64, String>> = resume_stream(
+            &policy,
+            |_: Option<&u64>| {
+                subscriptions += 1;
+                futures_util::stream::iter(vec![Err("connection reset".to_string())])
+            },
+            |_: &String| true,
+        )
+        .collect()
+        .await;
+        assert_eq!(items, vec![Err("connection reset".to_string())]);
+        assert_eq!(subscriptions, 3);
+    }
+
+    #[tokio::test]
+    async fn poll_until_ready() {
+        let mut checks = 0;
+        let res = three_quick_attempts()
+            .poll_until(async || {
+                checks += 1;
+                if checks == 3 { PollResult::<u64, ()>::Ready(checks) } else { PollResult::Pending }
+            })
+            .await;
+        assert_eq!(res, Ok(3));
+
+        let res = poll_until(&three_quick_attempts(), async || PollResult::<(), &str>::Failed("cancelled")).await;
+        assert_eq!(res, Err(PollError::Failed("cancelled")));
+
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Unlimited)
+            .backoff_policy(constant_backoff)
+            .base_delay(20)
+            .deadline(50)
+            .build();
+        let res = policy.poll_until(async || PollResult::<(), ()>::Pending).await;
+        match res {
+            Err(PollError::TimedOut { attempts, elapsed }) => {
+                assert_eq!(attempts, 3);
+                assert!(elapsed >= Duration::from_millis(40));
+            }
+            other => panic!("expected a timeout, got {other:?}"),
+        }
+    }
+
+    fn rate_limited_policy() -> RetryPolicy {
+        static LIMITER: std::sync::OnceLock<RateLimiter> = std::sync::OnceLock::new();
+        let limiter = LIMITER.get_or_init(|| RateLimiter::new(1, Duration::from_millis(40)));
+        RetryPolicy::builder()
+            .limit(RetryLimit::Limited(3))
+            .backoff_policy(constant_backoff)
+            .base_delay(0)
+            .rate_limiter(limiter.clone())
+            .build()
+    }
+
+    #[retry(rate_limited_policy)]
+    async fn rate_limited(agent: MutableAgent) -> RetryResult<(), ()> {
+        match agent.execute().await {
+            Ok(_) => Success(()),
+            Err(_) => Retry(()),
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_paces_every_attempt() {
+        /* 5 permits every 100ms without a burst: 1 permit every 20ms */
+        let limiter = RateLimiter::new(5, Duration::from_millis(100)).with_burst(1);
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Limited(3))
+            .backoff_policy(constant_backoff)
+            .base_delay(0)
+            .rate_limiter(limiter.clone())
+            .build();
+        let started = std::time::Instant::now();
+        let first = policy.call_closure(async || Retry::<(), ()>(()));
+        let clone = policy.clone();
+        let second = clone.call_closure(async || Retry::<(), ()>(()));
+        let (first, second) = tokio::join!(first, second);
+        assert!(first.is_err() && second.is_err());
+        /* 6 attempts shared one limiter, the first was immediate */
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        /* a burst lets the first attempts through at once */
+        let limiter = RateLimiter::new(3, Duration::from_secs(10));
+        let started = std::time::Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        /* generated retry loops acquire a permit before each attempt too */
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let started = std::time::Instant::now();
+        assert!(rate_limited(agent.clone()).await.is_err());
+        assert_eq!(agent.count().await, 3);
+        assert!(started.elapsed() >= Duration::from_millis(80));
+        assert!(rate_limited_policy().to_string().ends_with(", rate limited"));
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_wait_is_bounded_by_the_deadline() {
+        /* the first attempt takes the only permit of the next 10 seconds */
+        let limiter = RateLimiter::new(1, Duration::from_secs(10));
+        let policy = RetryPolicy::builder()
+            .limit(RetryLimit::Unlimited)
+            .backoff_policy(constant_backoff)
+            .base_delay(0)
+            .deadline_duration(Duration::from_millis(200))
+            .rate_limiter(limiter.clone())
+            .build();
+        let attempts = std::sync::atomic::AtomicU64::new(0);
+        let started = std::time::Instant::now();
+        let result = policy
+            .call_closure(async || {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Retry::<(), u64>(attempts.load(std::sync::atomic::Ordering::SeqCst))
+            })
+            .await;
+        assert_eq!(result, Err(1));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        /* a permit that is not taken stays available */
+        let limiter = RateLimiter::new(1, Duration::from_millis(100));
+        limiter.acquire().await;
+        assert!(!limiter.acquire_within(Duration::from_millis(10)).await);
+        let started = std::time::Instant::now();
+        assert!(limiter.acquire_within(Duration::from_millis(500)).await);
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        /* bursts large enough to overflow the tolerance saturate instead */
+        let limiter = RateLimiter::new(1, Duration::from_secs(10_000_000_000)).with_burst(u32::MAX);
+        limiter.acquire().await;
+        limiter.acquire().await;
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum DbError {
+        Busy,
+        Rejected,
+    }
+
+    impl From<eztry::bulkhead::BulkheadRejected> for DbError {
+        fn from(_: eztry::bulkhead::BulkheadRejected) -> Self {
+            DbError::Rejected
+        }
+    }
+
+    fn exclusive_bulkhead() -> &'static Bulkhead {
+        static BULKHEAD: std::sync::OnceLock<Bulkhead> = std::sync::OnceLock::new();
+        BULKHEAD.get_or_init(|| Bulkhead::new(1).with_queue_timeout(Duration::ZERO))
+    }
+
+    #[retry(limit = 3, base_delay = 1, bulkhead = "tests-db")]
+    async fn query(
+        in_flight: Arc<std::sync::atomic::AtomicUsize>,
+        peak: Arc<std::sync::atomic::AtomicUsize>,
+        busy_once: Arc<std::sync::atomic::AtomicBool>,
+    ) -> RetryResult<(), DbError> {
+        use std::sync::atomic::Ordering::SeqCst;
+        let running = in_flight.fetch_add(1, SeqCst) + 1;
+        peak.fetch_max(running, SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        in_flight.fetch_sub(1, SeqCst);
+        if busy_once.swap(false, SeqCst) { Retry(DbError::Busy) } else { Success(()) }
+    }
+
+    #[retry(limit = 3, base_delay = 1, bulkhead = exclusive_bulkhead())]
+    async fn exclusive(delay: u64) -> RetryResult<u64, DbError> {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        Success(delay)
+    }
+
+    #[retry(limit = 3, base_delay = 1, bulkhead = "tests-unregistered")]
+    async fn unregistered_bulkhead() -> RetryResult<(), DbError> {
+        Success(())
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "no bulkhead is registered under the name 'tests-unregistered'")]
+    async fn unregistered_named_bulkhead_panics() {
+        let _ = unregistered_bulkhead().await;
+    }
+
+    #[tokio::test]
+    async fn bulkhead_caps_attempts_in_flight() {
+        global::set_named_bulkhead("tests-db", Bulkhead::new(2));
+        let in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let handles: Vec<_> = (0..6)
+            .map(|_| {
+                let busy_once = Arc::new(std::sync::atomic::AtomicBool::new(true));
+                tokio::spawn(query(in_flight.clone(), peak.clone(), busy_once))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), Ok(()));
+        }
+        assert_eq!(peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(global::get_named_bulkhead("tests-db").unwrap().available(), 2);
+        assert!(global::remove_named_bulkhead("tests-db"));
+
+        /* a full bulkhead rejects attempts once the queue timeout passes, without retrying them */
+        let (first, second) = tokio::join!(exclusive(50), exclusive(0));
+        assert_eq!(first, Ok(50));
+        assert_eq!(second, Err(DbError::Rejected));
+
+        let bulkhead = Bulkhead::new(1).with_queue_timeout(Duration::from_millis(20));
+        let slot = bulkhead.acquire().await.unwrap();
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysSucceed);
+        let fetch = Fetch::new(agent.clone(), "bulkhead");
+        let mut retryer = fetch.prepare();
+        retryer.set_policy(three_quick_attempts());
+        retryer.set_bulkhead_with(bulkhead.clone(), |rejected| rejected.max_concurrent as u64);
+        assert_eq!(retryer.run().await, Err(1));
+        assert_eq!(agent.count().await, 0);
+
+        drop(slot);
+        assert_eq!(retryer.run().await, Ok(8));
+        assert_eq!(bulkhead.available(), 1);
+        let rejected = Bulkhead::new(1).with_queue_timeout(Duration::ZERO);
+        let _held = rejected.acquire().await.unwrap();
+        let err = rejected.run(async {}).await.unwrap_err();
+        assert_eq!(err.max_concurrent, 1);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum PipelineError {
+        TimedOut,
+        Rejected,
+        Open,
+    }
+
+    impl From<eztry::timeout::TimeoutError> for PipelineError {
+        fn from(_: eztry::timeout::TimeoutError) -> Self {
+            PipelineError::TimedOut
+        }
+    }
+
+    impl From<BulkheadRejected> for PipelineError {
+        fn from(_: BulkheadRejected) -> Self {
+            PipelineError::Rejected
+        }
+    }
+
+    impl From<CircuitOpen> for PipelineError {
+        fn from(_: CircuitOpen) -> Self {
+            PipelineError::Open
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_layers_run_in_order() {
+        /* retry, then fallback once the attempts are exhausted */
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let pipeline = Pipeline::new()
+            .fallback(|e: u64| e as usize + 100)
+            .retry(three_quick_attempts());
+        assert_eq!(pipeline.run(&Fetch::new(agent.clone(), "fallback")).await, Ok(103));
+        assert_eq!(agent.count().await, 3);
+
+        /* the circuit opens after two failures and rejects the third attempt without running it */
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        let pipeline = Pipeline::new()
+            .retry(three_quick_attempts())
+            .circuit_breaker_with(breaker.clone(), |open| open.retry_after.as_millis() as u64 + 1000);
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysFail);
+        let res = pipeline.run(&Fetch::new(agent.clone(), "open")).await;
+        assert!(matches!(res, Err(v) if v > 1000));
+        assert_eq!(agent.count().await, 2);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        /* after the reset timeout a successful trial closes it again, the pipeline can be moved into a task */
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        let agent = FallibleAgent::mutable(FallibleBehaviour::AlwaysSucceed);
+        let fetch = Fetch::new(agent.clone(), "closed");
+        let res = tokio::spawn(async move { pipeline.run(&fetch).await }).await.unwrap();
+        assert_eq!(res, Ok(6));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        /* timed out attempts are retried, rejections by the bulkhead are final */
+        let bulkhead = Bulkhead::new(1).with_queue_timeout(Duration::ZERO);
+        let pipeline = Pipeline::new()
+            .bulkhead(bulkhead.clone())
+            .timeout(Duration::from_millis(10))
+            .retry(three_quick_attempts())
+            .circuit_breaker(CircuitBreaker::new(5, Duration::from_secs(1)));
+        let mut attempts = 0;
+        let res = pipeline
+            .call(async || {
+                attempts += 1;
+                let delay = if attempts < 3 { 50 } else { 0 };
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Success(attempts)
+            })
+            .await;
+        assert_eq!(res, Ok(3));
+
+        let _held = bulkhead.acquire().await.unwrap();
+        let res = pipeline.call(async || Success(0)).await;
+        assert_eq!(res, Err(PipelineError::Rejected));
+    }
+
+    #[tokio::test]
+    async fn bulkhead_rejections_are_not_recorded_by_the_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(30));
+        let bulkhead = Bulkhead::new(1).with_queue_timeout(Duration::ZERO);
+        let pipeline = Pipeline::new()
+            .bulkhead(bulkhead.clone())
+            .circuit_breaker(breaker.clone());
+
+        /* a rejection between two failures doesn't reset the count of consecutive failures */
+        assert_eq!(pipeline.call(async || Retry(PipelineError::TimedOut)).await, Err(PipelineError::TimedOut));
+        let held = bulkhead.acquire().await.unwrap();
+        assert_eq!(pipeline.call(async || Success(0)).await, Err(PipelineError::Rejected));
+        drop(held);
+        assert_eq!(pipeline.call(async || Retry(PipelineError::TimedOut)).await, Err(PipelineError::TimedOut));
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        /* a rejected trial doesn't close the circuit, and lets the next trial through */
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let held = bulkhead.acquire().await.unwrap();
+        assert_eq!(pipeline.call(async || Success(0)).await, Err(PipelineError::Rejected));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        drop(held);
+        assert_eq!(pipeline.call(async || Success(1)).await, Ok(1));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn stale_circuit_permits_are_ignored() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(30));
+
+        /* permits acquired while closed finish after the circuit opened */
+        let stale_success = breaker.try_acquire().unwrap();
+        let stale_failure = breaker.try_acquire().unwrap();
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        stale_success.success();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        /* or during the trial of another task: neither reopens the circuit nor lets a second trial through */
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let trial = breaker.try_acquire().unwrap();
+        stale_failure.failure();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire().is_err());
+
+        /* only the trial decides: a stale permit dropped or succeeding during the trial changes nothing */
+        trial.success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        let stale_success = breaker.try_acquire().unwrap();
+        let stale_drop = breaker.try_acquire().unwrap();
+        breaker.try_acquire().unwrap().failure();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let trial = breaker.try_acquire().unwrap();
+        drop(stale_drop);
+        assert!(breaker.try_acquire().is_err());
+        stale_success.success();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        trial.failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
 }